@@ -0,0 +1,464 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    path::Path,
+};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use clap::{Args, Parser, Subcommand};
+
+use rebalancing_data::prep::checksum::ChecksumPolicy;
+use rebalancing_data::prep::decimal::{format_fixed, format_time};
+use rebalancing_data::prep::delta::{decode_delta, encode_delta, DEFAULT_BLOCK_RECORDS};
+use rebalancing_data::prep::densify::{bitmap_path, densify, FillBitmap};
+use rebalancing_data::prep::export::{ExportFormat, ExportOptions, TimeFormat};
+use rebalancing_data::prep::frames::{compress_frames, decompress_frames, DEFAULT_FRAME_RECORDS, DEFAULT_ZSTD_LEVEL};
+use rebalancing_data::prep::gaps::{gap_report, CoveragePeriod};
+use rebalancing_data::prep::header::{read_header_unchecked, DEFAULT_PRICE_SCALE, DEFAULT_SYMBOL};
+use rebalancing_data::prep::inspect::{inspect, Section};
+use rebalancing_data::prep::manifest::Manifest;
+use rebalancing_data::prep::resample::{parse_interval, resample, ResampleOptions};
+use rebalancing_data::{
+    parse_binance, Encoding, FillPolicy, MergeOptions, OutputMode, OverlapPolicy, PriceFormat, PriceReader, PriceWidth,
+    Rounding, SolanaPriceEntry,
+};
+
+const DEFAULT_SOURCES: &str =
+    "./solana_data_1s/spot/monthly/klines/SOLUSDT/1s,./solana_data_1s/spot/daily/klines/SOLUSDT/1s";
+const DEFAULT_DEST: &str = "./solana_historical_price.dat";
+
+/** Builds and works with the merged price file. Defaults come from the environment or `.env`. */
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Merge the Binance kline dumps into the output file.
+    Build(BuildArgs),
+    /// Print the header and size of a file.
+    Info(FileArg),
+    /// Check a file's header, record order and side files.
+    Verify(FileArg),
+    /// Print the head, middle and tail of a file, or a time window.
+    Inspect(InspectArgs),
+    /// Look up the price at a time, or the records in a window.
+    Query(QueryArgs),
+    /// Write the records of a window out as CSV, JSON Lines or Parquet.
+    Export(ExportArgs),
+    /// Roll the records up into bars of a longer interval.
+    Resample(ResampleArgs),
+    /// List gaps and the coverage per day or month.
+    Gaps(GapsArgs),
+    /// Write a copy with one record per interval, gaps filled.
+    Densify(DensifyArgs),
+    /// Convert between raw records and the delta or zstd encoding.
+    Convert(ConvertArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct FileArg {
+    /// Merged `.dat` file.
+    #[arg(env = "SOLPRICE_DEST", default_value = DEFAULT_DEST)]
+    pub file: String,
+}
+
+#[derive(Debug, Args)]
+pub struct BuildArgs {
+    /// Folders of kline archives or CSVs, merged in this order.
+    #[arg(long = "source", env = "SOLPRICE_SOURCES", value_delimiter = ',', default_value = DEFAULT_SOURCES)]
+    pub sources: Vec<String>,
+    #[arg(long, env = "SOLPRICE_DEST", default_value = DEFAULT_DEST)]
+    pub dest: String,
+    #[arg(long, env = "SOLPRICE_SYMBOL", default_value = DEFAULT_SYMBOL)]
+    pub symbol: String,
+    /// Kline interval of the sources, e.g. 1s or 1m.
+    #[arg(long, env = "SOLPRICE_INTERVAL", default_value = "1s", value_parser = interval_arg)]
+    pub interval: u32,
+    /// Decimals kept from the prices, 0 to 8.
+    #[arg(long, env = "SOLPRICE_SCALE", default_value_t = DEFAULT_PRICE_SCALE)]
+    pub scale: u8,
+    /// Bytes per stored price, 4 or 8.
+    #[arg(long, env = "SOLPRICE_WIDTH", default_value = "4")]
+    pub width: PriceWidth,
+    /// close, ohlcv, arrow, sqlite, delta or zstd.
+    #[arg(long, default_value = "close")]
+    pub output: OutputMode,
+    /// truncate, half-up or half-even, must match an existing output.
+    #[arg(long, default_value = "half-up")]
+    pub rounding: Rounding,
+    /// refuse or quarantine.
+    #[arg(long, default_value = "refuse")]
+    pub checksum: ChecksumPolicy,
+    /// Sources overlapping the existing output: error, skip or overwrite.
+    #[arg(long, default_value = "skip")]
+    pub overlap: OverlapPolicy,
+    /// Truncate a torn trailing record instead of refusing the output.
+    #[arg(long)]
+    pub repair: bool,
+    /// Files parsed at once, 0 for one per CPU core, up to 8.
+    #[arg(long, env = "SOLPRICE_JOBS", default_value_t = 0)]
+    pub jobs: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    #[command(flatten)]
+    pub file: FileArg,
+    /// Records printed for each of head, middle and tail.
+    #[arg(long, default_value_t = 50)]
+    pub rows: usize,
+    #[arg(long)]
+    pub head: bool,
+    #[arg(long)]
+    pub middle: bool,
+    #[arg(long)]
+    pub tail: bool,
+    #[command(flatten)]
+    pub window: Window,
+}
+
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    #[command(flatten)]
+    pub file: FileArg,
+    /// Exact time, epoch seconds or UTC like 2024-01-31T12:00:00.
+    #[arg(long, value_parser = time_arg, conflicts_with_all = ["from", "to"])]
+    pub at: Option<u32>,
+    #[command(flatten)]
+    pub window: Window,
+}
+
+/** Half-open time window, open ended where not given. */
+#[derive(Debug, Args)]
+pub struct Window {
+    /// First time included, epoch seconds or UTC.
+    #[arg(long, value_parser = time_arg)]
+    pub from: Option<u32>,
+    /// First time excluded, epoch seconds or UTC.
+    #[arg(long, value_parser = time_arg)]
+    pub to: Option<u32>,
+}
+
+impl Window {
+    fn bounds(&self) -> (u32, u32) {
+        (self.from.unwrap_or(0), self.to.unwrap_or(u32::MAX))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub file: FileArg,
+    #[arg(long)]
+    pub out: String,
+    /// csv, jsonl or parquet, by default from the extension of --out.
+    #[arg(long)]
+    pub format: Option<ExportFormat>,
+    /// epoch or iso.
+    #[arg(long, default_value = "epoch")]
+    pub time: TimeFormat,
+    #[command(flatten)]
+    pub window: Window,
+}
+
+#[derive(Debug, Args)]
+pub struct ResampleArgs {
+    #[command(flatten)]
+    pub file: FileArg,
+    /// Bar size, e.g. 90, 30s, 5m, 1h or 1d.
+    #[arg(long, value_parser = interval_arg)]
+    pub interval: u32,
+    /// Shift of the bar starts from the UTC epoch, same units.
+    #[arg(long, default_value = "0", value_parser = interval_arg)]
+    pub offset: u32,
+    #[arg(long)]
+    pub out: String,
+}
+
+#[derive(Debug, Args)]
+pub struct GapsArgs {
+    #[command(flatten)]
+    pub file: FileArg,
+    /// Only list gaps of more missing seconds than this.
+    #[arg(long, default_value_t = 0)]
+    pub longer_than: u32,
+    /// Coverage per month instead of per day.
+    #[arg(long)]
+    pub monthly: bool,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DensifyArgs {
+    #[command(flatten)]
+    pub file: FileArg,
+    /// ffill, linear or sentinel.
+    #[arg(long)]
+    pub fill: FillPolicy,
+    #[arg(long)]
+    pub out: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ConvertArgs {
+    #[command(flatten)]
+    pub file: FileArg,
+    /// raw, delta or zstd.
+    #[arg(long)]
+    pub to: Encoding,
+    /// Records per delta block or zstd frame, more compresses better and makes lookups slower.
+    /// Defaults to 4096 for delta, 65536 for zstd.
+    #[arg(long)]
+    pub block_records: Option<u32>,
+    /// zstd compression level, 1 to 22.
+    #[arg(long, default_value_t = DEFAULT_ZSTD_LEVEL)]
+    pub level: i32,
+    #[arg(long)]
+    pub out: String,
+}
+
+fn interval_arg(value: &str) -> Result<u32, String> {
+    parse_interval(value).map_err(|err| err.to_string())
+}
+
+// Epoch seconds, a date, or a UTC date and time with `T` or a space.
+fn time_arg(value: &str) -> Result<u32, String> {
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        return value.parse().map_err(|_| format!("Time {:?} is out of range", value));
+    }
+
+    let value = value.trim_end_matches('Z');
+    let time = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))
+        .ok_or_else(|| format!("Invalid time {:?}, use epoch seconds or 2024-01-31T12:00:00", value))?;
+
+    u32::try_from(time.and_utc().timestamp()).map_err(|_| format!("Time {:?} is out of range", value))
+}
+
+pub fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Build(args) => build(args),
+        Command::Info(args) => info(&args.file),
+        Command::Verify(args) => verify(&args.file),
+        Command::Inspect(args) => {
+            let mut sections = Vec::new();
+            if args.head {
+                sections.push(Section::Head);
+            }
+            if args.middle {
+                sections.push(Section::Middle);
+            }
+            if args.tail {
+                sections.push(Section::Tail);
+            }
+            if args.window.from.is_some() || args.window.to.is_some() {
+                let (from, to) = args.window.bounds();
+                sections.push(Section::Window { from, to });
+            }
+            // Nothing picked, the same overview the merge used to print.
+            if sections.is_empty() {
+                sections = vec![Section::Head, Section::Middle, Section::Tail];
+            }
+            Ok(inspect(&args.file.file, &sections, args.rows)?)
+        }
+        Command::Query(args) => query(args),
+        Command::Export(args) => export(args),
+        Command::Resample(args) => {
+            let options = ResampleOptions { interval_secs: args.interval, offset_secs: args.offset };
+            resample(&args.file.file, &args.out, &options)?;
+            Ok(())
+        }
+        Command::Gaps(args) => {
+            let period = if args.monthly { CoveragePeriod::Month } else { CoveragePeriod::Day };
+            let report = gap_report(&args.file.file, args.longer_than, period)?;
+            if args.json {
+                println!("{}", report.to_json());
+            } else {
+                report.print_table();
+            }
+            Ok(())
+        }
+        Command::Densify(args) => {
+            densify(&args.file.file, &args.out, args.fill)?;
+            Ok(())
+        }
+        Command::Convert(args) => {
+            let source = read_header_unchecked(&mut File::open(&args.file.file)?)?
+                .map_or(Encoding::Raw, |header| header.encoding);
+            let records = match (source, args.to) {
+                (Encoding::Raw, Encoding::Delta) => {
+                    encode_delta(&args.file.file, &args.out, args.block_records.unwrap_or(DEFAULT_BLOCK_RECORDS))?
+                }
+                (Encoding::Raw, Encoding::Zstd) => compress_frames(
+                    &args.file.file,
+                    &args.out,
+                    args.block_records.unwrap_or(DEFAULT_FRAME_RECORDS),
+                    args.level,
+                )?,
+                (Encoding::Delta, Encoding::Raw) => decode_delta(&args.file.file, &args.out)?,
+                (Encoding::Zstd, Encoding::Raw) => decompress_frames(&args.file.file, &args.out)?,
+                (from, to) => return Err(format!("Can't convert {:?} to {:?}, convert to raw first", from, to).into()),
+            };
+            println!(
+                "Converted {} records, {} to {} bytes",
+                records,
+                fs::metadata(&args.file.file)?.len(),
+                fs::metadata(&args.out)?.len()
+            );
+            Ok(())
+        }
+    }
+}
+
+fn build(args: BuildArgs) -> Result<(), Box<dyn Error>> {
+    let options = MergeOptions {
+        output: args.output,
+        symbol: args.symbol,
+        interval_secs: args.interval,
+        format: PriceFormat { scale: args.scale, width: args.width, rounding: args.rounding },
+        checksum_policy: args.checksum,
+        overlap_policy: args.overlap,
+        repair: args.repair,
+        jobs: args.jobs,
+    };
+    let sources: Vec<&str> = args.sources.iter().map(String::as_str).collect();
+
+    parse_binance(&args.dest, &sources, &options).inspect_err(|err| {
+        eprintln!("Error in merging: {}", err);
+    })?;
+    Ok(())
+}
+
+fn info(path: &str) -> Result<(), Box<dyn Error>> {
+    let header = read_header_unchecked(&mut File::open(path)?)?;
+    println!("File:      {} ({} bytes)", path, fs::metadata(path)?.len());
+
+    match &header {
+        Some(header) => {
+            println!("Version:   {}", header.version);
+            println!("Symbol:    {}", header.symbol);
+            println!("Interval:  {}s", header.interval_secs);
+            println!("Layout:    {:?}, {} byte records", header.layout, header.record_size());
+            println!("Prices:    {}", header.format());
+            if let Some(fill) = header.fill {
+                println!("Densified: {:?}", fill);
+            }
+        }
+        None => println!("Legacy headerless file, close records with 3 decimals"),
+    }
+
+    let reader = PriceReader::open(path)?;
+    match reader.encoding() {
+        Encoding::Raw => {}
+        Encoding::Delta => println!("Encoding:  delta, {} blocks", reader.block_count()),
+        Encoding::Zstd => println!("Encoding:  zstd, {} frames", reader.block_count()),
+    }
+    println!("Records:   {}", reader.len());
+    if let (Some(first), Some(last)) = (reader.first()?, reader.last()?) {
+        println!("First:     {} ({})", format_time(first.time), first.time);
+        println!("Last:      {} ({})", format_time(last.time), last.time);
+    }
+
+    let manifest = Manifest::load(path)?;
+    if !manifest.entries.is_empty() {
+        println!("Manifest:  {} source files", manifest.entries.len());
+    }
+    Ok(())
+}
+
+fn verify(path: &str) -> Result<(), Box<dyn Error>> {
+    // Opening checks the header against the file length, or the block index of encoded files.
+    let reader = PriceReader::open(path)?;
+    let mut problems = Vec::new();
+
+    let mut previous: Option<SolanaPriceEntry> = None;
+    for (index, entry) in reader.iter().enumerate() {
+        let entry = entry?;
+        if let Some(previous) = previous.filter(|previous| previous.time >= entry.time) {
+            problems.push(format!(
+                "Record {} at {} doesn't come after {}",
+                index, format_time(entry.time), format_time(previous.time)
+            ));
+        }
+        previous = Some(entry);
+    }
+
+    if let (Some(header), Some(first), Some(last)) = (reader.header(), reader.first()?, reader.last()?) {
+        if header.first_time != first.time || header.last_time != last.time {
+            problems.push(format!(
+                "Header spans {} to {}, records span {} to {}",
+                header.first_time, header.last_time, first.time, last.time
+            ));
+        }
+    }
+
+    let manifest = Manifest::load(path)?;
+    if let Some(committed) = manifest.committed_records() {
+        if committed != reader.len() as u64 {
+            problems.push(format!("Manifest records {} entries, file holds {}", committed, reader.len()));
+        }
+    }
+
+    if reader.header().is_some_and(|header| header.fill.is_some()) {
+        if !Path::new(&bitmap_path(path)).exists() {
+            problems.push(format!("Densified file without its bitmap {}", bitmap_path(path)));
+        } else if FillBitmap::load(path)?.as_bytes().len() != reader.len().div_ceil(8) {
+            problems.push(format!("Bitmap {} doesn't match the record count", bitmap_path(path)));
+        }
+    }
+
+    if problems.is_empty() {
+        println!("{}: {} records OK", path, reader.len());
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    Err(format!("{} failed verification with {} problems", path, problems.len()).into())
+}
+
+fn price_scale(reader: &PriceReader) -> u8 {
+    reader.header().map_or(DEFAULT_PRICE_SCALE, |header| header.price_scale)
+}
+
+fn query(args: QueryArgs) -> Result<(), Box<dyn Error>> {
+    // Encoded files decode only the blocks or frames the query touches.
+    let reader = PriceReader::open(&args.file.file)?;
+    let scale = price_scale(&reader);
+    let (from, to) = args.window.bounds();
+
+    match args.at {
+        Some(time) => print_price(time, reader.price_at(time)?, scale),
+        None => {
+            for entry in reader.range(from, to)? {
+                let entry = entry?;
+                print_price(entry.time, Some(entry.close_price), scale);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_price(time: u32, price: Option<u64>, scale: u8) {
+    match price {
+        Some(price) => println!("{}  {}", format_time(time), format_fixed(price, scale)),
+        None => println!("{}  no record", format_time(time)),
+    }
+}
+
+fn export(args: ExportArgs) -> Result<(), Box<dyn Error>> {
+    let (from, to) = args.window.bounds();
+    let format = args.format.unwrap_or_else(|| ExportFormat::for_path(&args.out));
+    let options = ExportOptions { format, time: args.time, from, to };
+    let count = rebalancing_data::prep::export::export(&args.file.file, &args.out, &options)?;
+
+    println!("Exported {} records to {}", count, args.out);
+    Ok(())
+}
@@ -0,0 +1,19 @@
+/*!
+ * Builds and reads the merged Binance price history: kline parsing, the `.dat` writer and reader,
+ * and the tools on top of them. The modules are in `prep`, the types most callers need are re-exported here.
+ */
+
+pub mod prep;
+
+pub use prep::decimal::Rounding;
+pub use prep::delta::DeltaWriter;
+pub use prep::error::Error;
+pub use prep::frames::FrameWriter;
+pub use prep::header::{Encoding, FileHeader, FillPolicy, PriceFormat, PriceWidth, RecordLayout};
+pub use prep::kline::{get_ohlcv_vector, get_vector, ParseOptions};
+pub use prep::merge::{
+    parse_binance, read_binary_file, read_ohlcv_file, write_ohlcv_to_file, write_records, write_to_file, MergeOptions,
+    OutputMode, OverlapPolicy,
+};
+pub use prep::reader::{Entries, PriceReader, Records};
+pub use prep::record::{OhlcvEntry, Record, SolanaPriceEntry};
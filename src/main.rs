@@ -1,19 +1,12 @@
 use std::{error::Error};
 
-pub mod prep;
-pub use prep::*;
+use clap::Parser;
 
-fn main() -> Result<(), Box<dyn Error>> {
-
-    let source = ["./solana_data_1s/spot/monthly/klines/SOLUSDT/1s","./solana_data_1s/spot/daily/klines/SOLUSDT/1s"];
-    let dest = "./solana_historical_price.dat";
-    
-    parse_binance(dest, &source).or_else(| err| {
-        eprintln!("Error in merging: {}", err);
-        Err(err)
-    })?;   
+mod cli;
 
-    sample_readouts()?;
+fn main() -> Result<(), Box<dyn Error>> {
+    // Defaults for paths, symbol and precision can live in `.env`.
+    dotenv::dotenv().ok();
 
-    Ok(())
-}
\ No newline at end of file
+    cli::run(cli::Cli::parse())
+}
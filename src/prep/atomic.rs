@@ -0,0 +1,33 @@
+use std::{
+    fs::{self, File},
+    path::Path,
+};
+
+use crate::prep::error::Error;
+
+/** Scratch path next to `dest`, on the same filesystem so the final rename is atomic. */
+pub(crate) fn temp_path(dest: &str) -> String {
+    format!("{}.tmp", dest)
+}
+
+/** Flushes `temp` to disk and renames it over `dest`, readers see the old or the new file, never half of one. */
+pub(crate) fn commit_temp(temp: &str, dest: &str) -> Result<(), Error> {
+    File::open(temp)?.sync_all()?;
+    fs::rename(temp, dest)?;
+
+    // The rename itself only survives a crash once the directory is synced.
+    let dir = Path::new(dest)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+/** Scratch file for a test, in the system temp folder. `name` has to be unique across tests, they run in parallel. */
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("rebalancing-data-{}-{}", std::process::id(), name));
+    path.to_string_lossy().into_owned()
+}
@@ -0,0 +1,136 @@
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::Path,
+    str::FromStr,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::prep::error::Error;
+
+/** What to do with an archive whose SHA-256 doesn't match its `.CHECKSUM` sidecar. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumPolicy {
+    #[default]
+    Refuse,     // Stop the merge with an error.
+    Quarantine, // Move the archive and its sidecar into a `quarantine` folder and carry on.
+}
+
+impl FromStr for ChecksumPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ChecksumPolicy, String> {
+        match name {
+            "refuse" => Ok(ChecksumPolicy::Refuse),
+            "quarantine" => Ok(ChecksumPolicy::Quarantine),
+            _ => Err(format!("Unknown checksum policy {:?}, use refuse or quarantine", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    Verified,
+    Missing,
+    Mismatch { expected: String, actual: String },
+}
+
+#[derive(Debug, Default)]
+pub struct ChecksumSummary {
+    pub files: Vec<(String, ChecksumStatus)>,
+}
+
+impl ChecksumSummary {
+    pub fn record(&mut self, file: &str, status: ChecksumStatus) {
+        self.files.push((file.to_string(), status));
+    }
+
+    pub fn count(&self, wanted: fn(&ChecksumStatus) -> bool) -> usize {
+        self.files.iter().filter(|(_, status)| wanted(status)).count()
+    }
+
+    pub fn print(&self) {
+        println!("\nChecksum summary:");
+        for (file, status) in self.files.iter() {
+            match status {
+                ChecksumStatus::Verified => println!("  verified  {}", file),
+                ChecksumStatus::Missing => println!("  missing   {}", file),
+                ChecksumStatus::Mismatch { expected, actual } => {
+                    println!("  FAILED    {} (expected {}, got {})", file, expected, actual)
+                }
+            }
+        }
+        println!(
+            "Verified: {}, missing checksum: {}, failed: {}",
+            self.count(|s| *s == ChecksumStatus::Verified),
+            self.count(|s| *s == ChecksumStatus::Missing),
+            self.count(|s| matches!(s, ChecksumStatus::Mismatch { .. })),
+        );
+    }
+}
+
+fn sidecar_path(path: &str) -> String {
+    format!("{}.CHECKSUM", path)
+}
+
+pub fn sha256_file(path: &str) -> Result<String, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    let mut hex = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    Ok(hex)
+}
+
+/** Checks a file's SHA-256 (see `sha256_file`) against `<file>.CHECKSUM`, formatted like `sha256sum` output. */
+pub fn verify_checksum(path: &str, actual: &str) -> Result<ChecksumStatus, Error> {
+    let sidecar = sidecar_path(path);
+    if !Path::new(&sidecar).is_file() {
+        return Ok(ChecksumStatus::Missing);
+    }
+
+    let contents = fs::read_to_string(&sidecar)?;
+    let expected = contents
+        .split_whitespace()
+        .next()
+        .filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| Error::Format(format!("Malformed checksum file {}", sidecar)))?
+        .to_lowercase();
+
+    if actual == expected {
+        Ok(ChecksumStatus::Verified)
+    } else {
+        Ok(ChecksumStatus::Mismatch { expected, actual: actual.to_string() })
+    }
+}
+
+/** Moves a file and its sidecar into a `quarantine` folder next to it. */
+pub(crate) fn quarantine(path: &str) -> Result<String, Error> {
+    let source = Path::new(path);
+    let folder = source.parent().unwrap_or(Path::new(".")).join("quarantine");
+    fs::create_dir_all(&folder)?;
+
+    let name = source.file_name().ok_or_else(|| Error::Format(format!("No file name in {}", path)))?;
+    let target = folder.join(name);
+    fs::rename(source, &target)?;
+
+    let sidecar = sidecar_path(path);
+    if Path::new(&sidecar).is_file() {
+        fs::rename(&sidecar, folder.join(format!("{}.CHECKSUM", name.to_string_lossy())))?;
+    }
+
+    Ok(target.to_string_lossy().to_string())
+}
@@ -0,0 +1,275 @@
+use std::{error::Error, fmt, str::FromStr};
+
+/** What to do with digits past the requested scale. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    Truncate = 0, // Drop them, 2.8559 at 3 decimals is 2.855.
+    #[default]
+    HalfUp = 1,   // Ties away from zero, 2.8555 is 2.856.
+    HalfEven = 2, // Banker's rounding, ties to the even digit, 2.8545 is 2.854 and 2.8555 is 2.856.
+}
+
+impl Rounding {
+    // Header byte, 0 is also what files from before rounding modes hold, and those truncated.
+    pub(crate) fn from_u8(value: u8) -> Option<Rounding> {
+        match value {
+            0 => Some(Rounding::Truncate),
+            1 => Some(Rounding::HalfUp),
+            2 => Some(Rounding::HalfEven),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecimalError {
+    Empty,
+    Negative,
+    Exponent,         // Scientific notation like 2.85e3.
+    InvalidChar(char),
+    MultipleDots,
+    Overflow,         // Doesn't fit a u64 at the requested scale.
+}
+
+impl fmt::Display for DecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecimalError::Empty => write!(f, "empty value"),
+            DecimalError::Negative => write!(f, "negative values are not supported"),
+            DecimalError::Exponent => write!(f, "scientific notation is not supported"),
+            DecimalError::InvalidChar(c) => write!(f, "invalid character {:?}", c),
+            DecimalError::MultipleDots => write!(f, "more than one decimal point"),
+            DecimalError::Overflow => write!(f, "value overflows at this scale"),
+        }
+    }
+}
+
+impl Error for DecimalError {}
+
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rounding::Truncate => write!(f, "truncate"),
+            Rounding::HalfUp => write!(f, "half-up"),
+            Rounding::HalfEven => write!(f, "half-even"),
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Rounding, String> {
+        match name {
+            "truncate" => Ok(Rounding::Truncate),
+            "half-up" => Ok(Rounding::HalfUp),
+            "half-even" => Ok(Rounding::HalfEven),
+            _ => Err(format!("Unknown rounding {:?}, use truncate, half-up or half-even", name)),
+        }
+    }
+}
+
+/** Seconds since epoch as `2024-01-31 12:00:00 UTC`, the number itself if chrono can't place it. */
+pub fn format_time(time: u32) -> String {
+    chrono::DateTime::from_timestamp(time as i64, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| time.to_string())
+}
+
+/** Fixed point integer with `scale` decimals back as a decimal string, 2850 at 3 decimals is "2.850". */
+pub fn format_fixed(value: u64, scale: u8) -> String {
+    if scale == 0 {
+        return value.to_string();
+    }
+    let divisor = 10u64.pow(scale as u32);
+    format!("{}.{:0width$}", value / divisor, value % divisor, width = scale as usize)
+}
+
+/**
+ * Parses a plain decimal string into a fixed point integer with `scale` decimals, without allocating.
+ * "2", "2.85" and "2.85000000" all give the same value, missing fraction digits count as zeros.
+ */
+pub fn parse_fixed(value: &str, scale: u8, rounding: Rounding) -> Result<u64, DecimalError> {
+    let bytes = value.as_bytes();
+    let bytes = match bytes.first() {
+        Some(b'+') => &bytes[1..],
+        Some(b'-') => return Err(DecimalError::Negative),
+        _ => bytes,
+    };
+
+    let mut result: u64 = 0;
+    let mut digits = 0;          // Digits seen in total, "." alone isn't a number.
+    let mut fraction: Option<u8> = None; // Fraction digits kept so far, once past the dot.
+    let mut first_dropped: Option<u8> = None;
+    let mut sticky = false;      // Any non-zero digit after the first dropped one.
+
+    for &byte in bytes {
+        match byte {
+            b'0'..=b'9' => {
+                digits += 1;
+                let digit = byte - b'0';
+                match fraction {
+                    Some(kept) if kept >= scale => match first_dropped {
+                        None => first_dropped = Some(digit),
+                        Some(_) => sticky |= digit != 0,
+                    },
+                    _ => {
+                        result = result
+                            .checked_mul(10)
+                            .and_then(|r| r.checked_add(digit as u64))
+                            .ok_or(DecimalError::Overflow)?;
+                        if let Some(kept) = fraction.as_mut() {
+                            *kept += 1;
+                        }
+                    }
+                }
+            }
+            b'.' if fraction.is_none() => fraction = Some(0),
+            b'.' => return Err(DecimalError::MultipleDots),
+            b'e' | b'E' => return Err(DecimalError::Exponent),
+            b'-' => return Err(DecimalError::Negative),
+            _ => return Err(DecimalError::InvalidChar(byte as char)),
+        }
+    }
+
+    if digits == 0 {
+        return Err(DecimalError::Empty);
+    }
+
+    // Pad short fractions up to the scale.
+    for _ in fraction.unwrap_or(0)..scale {
+        result = result.checked_mul(10).ok_or(DecimalError::Overflow)?;
+    }
+
+    let round_up = match (rounding, first_dropped) {
+        (_, None) | (Rounding::Truncate, _) => false,
+        (Rounding::HalfUp, Some(digit)) => digit >= 5,
+        (Rounding::HalfEven, Some(digit)) => digit > 5 || (digit == 5 && (sticky || result % 2 == 1)),
+    };
+
+    if round_up {
+        result = result.checked_add(1).ok_or(DecimalError::Overflow)?;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every way the readme note says Binance writes the same price: no fraction, trimmed, 6 and 8 decimals.
+    const BINANCE_FORMATS: [&str; 4] = ["2", "2.85", "2.850000", "2.85000000"];
+
+    #[test]
+    fn binance_formats_agree_at_every_scale() {
+        for scale in 0..=8u8 {
+            for rounding in [Rounding::Truncate, Rounding::HalfUp, Rounding::HalfEven] {
+                let whole = parse_fixed("2", scale, rounding).unwrap();
+                assert_eq!(whole, 2 * 10u64.pow(scale as u32));
+
+                let expected = parse_fixed("2.85", scale, rounding).unwrap();
+                for value in &BINANCE_FORMATS[1..] {
+                    assert_eq!(parse_fixed(value, scale, rounding).unwrap(), expected, "{} at scale {}", value, scale);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn binance_formats_at_each_scale() {
+        let expected = [(0, 2, 3), (1, 28, 29), (2, 285, 285), (8, 285_000_000, 285_000_000)];
+        for (scale, truncated, rounded) in expected {
+            for value in &BINANCE_FORMATS[1..] {
+                assert_eq!(parse_fixed(value, scale, Rounding::Truncate).unwrap(), truncated);
+                assert_eq!(parse_fixed(value, scale, Rounding::HalfUp).unwrap(), rounded);
+            }
+        }
+    }
+
+    #[test]
+    fn ties() {
+        assert_eq!(parse_fixed("2.8555", 3, Rounding::Truncate).unwrap(), 2855);
+        assert_eq!(parse_fixed("2.8555", 3, Rounding::HalfUp).unwrap(), 2856);
+        assert_eq!(parse_fixed("2.8555", 3, Rounding::HalfEven).unwrap(), 2856);
+
+        assert_eq!(parse_fixed("2.8545", 3, Rounding::Truncate).unwrap(), 2854);
+        assert_eq!(parse_fixed("2.8545", 3, Rounding::HalfUp).unwrap(), 2855);
+        assert_eq!(parse_fixed("2.8545", 3, Rounding::HalfEven).unwrap(), 2854);
+
+        // Trailing zeros keep a tie a tie, anything else past the 5 breaks it.
+        assert_eq!(parse_fixed("2.85450000", 3, Rounding::HalfEven).unwrap(), 2854);
+        assert_eq!(parse_fixed("2.85450001", 3, Rounding::HalfEven).unwrap(), 2855);
+        assert_eq!(parse_fixed("2.8544999", 3, Rounding::HalfUp).unwrap(), 2854);
+        assert_eq!(parse_fixed("0.5", 0, Rounding::HalfEven).unwrap(), 0);
+        assert_eq!(parse_fixed("1.5", 0, Rounding::HalfEven).unwrap(), 2);
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(parse_fixed("18446744073709551615", 0, Rounding::Truncate).unwrap(), u64::MAX);
+        assert_eq!(parse_fixed("18446744073709551616", 0, Rounding::Truncate), Err(DecimalError::Overflow));
+        assert_eq!(parse_fixed("18446744073709551615.5", 0, Rounding::HalfUp), Err(DecimalError::Overflow));
+        assert_eq!(parse_fixed("18446744073709551615.5", 0, Rounding::Truncate).unwrap(), u64::MAX);
+        // Fits as written, overflows once padded to the scale.
+        assert_eq!(parse_fixed("184467440737", 8, Rounding::Truncate).unwrap(), 18_446_744_073_700_000_000);
+        assert_eq!(parse_fixed("184467440738", 8, Rounding::Truncate), Err(DecimalError::Overflow));
+    }
+
+    #[test]
+    fn refused_inputs() {
+        for rounding in [Rounding::Truncate, Rounding::HalfUp, Rounding::HalfEven] {
+            assert_eq!(parse_fixed("2.85e3", 3, rounding), Err(DecimalError::Exponent));
+            assert_eq!(parse_fixed("2.85E3", 3, rounding), Err(DecimalError::Exponent));
+            assert_eq!(parse_fixed("-2.85", 3, rounding), Err(DecimalError::Negative));
+            assert_eq!(parse_fixed("2.8-5", 3, rounding), Err(DecimalError::Negative));
+            assert_eq!(parse_fixed("", 3, rounding), Err(DecimalError::Empty));
+            assert_eq!(parse_fixed(".", 3, rounding), Err(DecimalError::Empty));
+            assert_eq!(parse_fixed("+", 3, rounding), Err(DecimalError::Empty));
+            assert_eq!(parse_fixed("2.8.5", 3, rounding), Err(DecimalError::MultipleDots));
+            assert_eq!(parse_fixed("2,85", 3, rounding), Err(DecimalError::InvalidChar(',')));
+            assert_eq!(parse_fixed(" 2.85", 3, rounding), Err(DecimalError::InvalidChar(' ')));
+        }
+        assert_eq!(parse_fixed("+2.85", 2, Rounding::Truncate).unwrap(), 285);
+        assert_eq!(parse_fixed(".5", 1, Rounding::Truncate).unwrap(), 5);
+        assert_eq!(parse_fixed("5.", 1, Rounding::Truncate).unwrap(), 50);
+    }
+
+    // SplitMix64, enough to spread values over the whole u64 range without a dependency.
+    fn next(state: &mut u64) -> u64 {
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    #[test]
+    fn format_parse_round_trip() {
+        let mut state = 0x5eed;
+        for round in 0..20_000 {
+            let scale = (round % 9) as u8;
+            // Small, mid and full range values, so short and long integer parts both show up.
+            let value = match round % 3 {
+                0 => next(&mut state) % 100_000,
+                1 => next(&mut state) >> 24,
+                _ => next(&mut state),
+            };
+            let text = format_fixed(value, scale);
+            for rounding in [Rounding::Truncate, Rounding::HalfUp, Rounding::HalfEven] {
+                assert_eq!(parse_fixed(&text, scale, rounding), Ok(value), "{} at scale {}", text, scale);
+            }
+
+            // Extra trailing zeros, the way Binance pads, change nothing.
+            let padded = if scale == 0 { format!("{}.000", text) } else { format!("{}000", text) };
+            assert_eq!(parse_fixed(&padded, scale, Rounding::HalfEven), Ok(value));
+        }
+    }
+
+    #[test]
+    fn times_print_in_utc() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_time(1_706_702_400), "2024-01-31 12:00:00 UTC");
+        assert_eq!(format_time(u32::MAX), "2106-02-07 06:28:15 UTC");
+    }
+}
@@ -0,0 +1,472 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use crate::prep::atomic::{commit_temp, temp_path};
+use crate::prep::error::Error;
+use crate::prep::header::{write_header, Encoding, FileHeader, PriceWidth, RecordLayout, HEADER_SIZE};
+use crate::prep::reader::{decode_to_raw, Block, PriceReader};
+use crate::prep::record::{Record, SolanaPriceEntry};
+
+// Layout of a delta encoded file, all integers little-endian:
+//
+//  header     64 bytes, `Encoding::Delta`, record count and time span filled in
+//  blocks     one per `block_records` records, back to back
+//  index      per block: byte offset (u64), first time (u32), first price (u64), record count (u32)
+//  footer     index offset (u64), records per block (u32), block count (u32)
+//
+// The first record of a block is in the index. Every further record is the time delta as an unsigned
+// varint followed by the price delta, zig-zag coded, as a varint. A steady 1s series takes 2 bytes a record.
+
+pub const DEFAULT_BLOCK_RECORDS: u32 = 4096;
+
+const INDEX_ENTRY_SIZE: usize = 24;
+const FOOTER_SIZE: usize = 16;
+
+// Records encoded per chunk.
+const CHUNK_RECORDS: usize = 1 << 16;
+
+/** Where a block starts and the record it starts with. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlockIndexEntry {
+    offset: u64,
+    first_time: u32,
+    first_price: u64,
+    count: u32,
+}
+
+impl BlockIndexEntry {
+    fn to_bytes(self) -> [u8; INDEX_ENTRY_SIZE] {
+        let mut buf = [0u8; INDEX_ENTRY_SIZE];
+        buf[0..8].copy_from_slice(&self.offset.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.first_time.to_le_bytes());
+        buf[12..20].copy_from_slice(&self.first_price.to_le_bytes());
+        buf[20..24].copy_from_slice(&self.count.to_le_bytes());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Result<BlockIndexEntry, Error> {
+        Ok(BlockIndexEntry {
+            offset: u64::from_le_bytes(buf[0..8].try_into()?),
+            first_time: u32::from_le_bytes(buf[8..12].try_into()?),
+            first_price: u64::from_le_bytes(buf[12..20].try_into()?),
+            count: u32::from_le_bytes(buf[20..24].try_into()?),
+        })
+    }
+}
+
+/** Footer of a block encoded file: index offset, records per block and block count. */
+pub(crate) fn write_footer<W: Write>(writer: &mut W, index_offset: u64, block_records: u32, blocks: usize) -> Result<(), Error> {
+    writer.write_all(&index_offset.to_le_bytes())?;
+    writer.write_all(&block_records.to_le_bytes())?;
+    writer.write_all(&(blocks as u32).to_le_bytes())?;
+    Ok(())
+}
+
+/** Reads the footer and returns the index bytes and records per block, checking both fit the file. */
+fn read_footer<'a>(bytes: &'a [u8], path: &str, entry_size: usize) -> Result<(&'a [u8], u32), Error> {
+    if bytes.len() < HEADER_SIZE + FOOTER_SIZE {
+        return Err(Error::Format(format!("{} is too short for its footer", path)));
+    }
+
+    let footer = &bytes[bytes.len() - FOOTER_SIZE..];
+    let index_offset = u64::from_le_bytes(footer[0..8].try_into()?);
+    let block_records = u32::from_le_bytes(footer[8..12].try_into()?);
+    let block_count = u32::from_le_bytes(footer[12..16].try_into()?) as u64;
+    // The footer is untrusted, a wild offset or count mustn't overflow.
+    let index_end = block_count.checked_mul(entry_size as u64).and_then(|size| size.checked_add(index_offset));
+    if index_offset < HEADER_SIZE as u64 || index_end != Some((bytes.len() - FOOTER_SIZE) as u64) {
+        return Err(Error::Format(format!("{} has a block index that doesn't fit the file", path)));
+    }
+    Ok((&bytes[index_offset as usize..bytes.len() - FOOTER_SIZE], block_records))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/** Varint at the start of `bytes` and its length, `None` when it runs off the end or past 64 bits. */
+fn read_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (index, &byte) in bytes.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * index);
+        if byte < 0x80 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+// Price deltas wrap, so even u64 prices round-trip.
+fn zigzag(delta: u64) -> u64 {
+    let delta = delta as i64;
+    ((delta << 1) ^ (delta >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> u64 {
+    (value >> 1) ^ (value & 1).wrapping_neg()
+}
+
+/**
+ * Streams sorted close records into a delta encoded file. Blocks are written as they fill up,
+ * `finish` adds the index and footer and fills in the header.
+ */
+pub struct DeltaWriter {
+    path: String,
+    file: BufWriter<File>,
+    header: FileHeader,
+    block_records: u32,
+    index: Vec<BlockIndexEntry>,
+    block: Vec<u8>,
+    offset: u64,
+    previous: Option<SolanaPriceEntry>,
+}
+
+impl DeltaWriter {
+    /** Takes symbol, interval and price format from `header`, it must be for the close layout. */
+    pub fn create(path: &str, header: &FileHeader, block_records: u32) -> Result<DeltaWriter, Error> {
+        if header.layout != RecordLayout::Close {
+            return Err(Error::Format(format!("Delta encoding only holds close records, not {:?}", header.layout)));
+        }
+        if block_records == 0 {
+            return Err(Error::Format("Blocks need at least one record".to_string()));
+        }
+
+        let header = FileHeader { encoding: Encoding::Delta, record_count: 0, first_time: 0, last_time: 0, ..header.clone() };
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&header.to_bytes()?)?;
+
+        Ok(DeltaWriter {
+            path: path.to_string(),
+            file,
+            header,
+            block_records,
+            index: Vec::new(),
+            block: Vec::new(),
+            offset: HEADER_SIZE as u64,
+            previous: None,
+        })
+    }
+
+    pub fn write(&mut self, entries: &[SolanaPriceEntry]) -> Result<(), Error> {
+        for &entry in entries {
+            match self.previous {
+                _ if entry.close_price > self.header.price_width.max_value() => {
+                    return Err(Error::Format(format!(
+                        "Price {} at {} doesn't fit {:?} storage",
+                        entry.close_price, entry.time, self.header.price_width
+                    )));
+                }
+                Some(previous) if entry.time <= previous.time => {
+                    return Err(Error::Timeline { path: self.path.clone(), last: previous.time, next: entry.time });
+                }
+                Some(previous) if self.index.last().is_some_and(|block| block.count < self.block_records) => {
+                    write_varint(&mut self.block, (entry.time - previous.time) as u64);
+                    write_varint(&mut self.block, zigzag(entry.close_price.wrapping_sub(previous.close_price)));
+                }
+                _ => {
+                    self.flush_block()?;
+                    self.index.push(BlockIndexEntry {
+                        offset: self.offset,
+                        first_time: entry.time,
+                        first_price: entry.close_price,
+                        count: 0,
+                    });
+                    if self.previous.is_none() {
+                        self.header.first_time = entry.time;
+                    }
+                }
+            }
+            if let Some(block) = self.index.last_mut() {
+                block.count += 1;
+            }
+            self.header.record_count += 1;
+            self.header.last_time = entry.time;
+            self.previous = Some(entry);
+        }
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> Result<(), Error> {
+        self.file.write_all(&self.block)?;
+        self.offset += self.block.len() as u64;
+        self.block.clear();
+        Ok(())
+    }
+
+    /** Writes the last block, index and footer, returns the number of records written. */
+    pub fn finish(mut self) -> Result<u64, Error> {
+        self.flush_block()?;
+
+        let index_offset = self.offset;
+        for block in &self.index {
+            self.file.write_all(&block.to_bytes())?;
+        }
+        write_footer(&mut self.file, index_offset, self.block_records, self.index.len())?;
+
+        let mut file = self.file.into_inner().map_err(|err| Error::Io(err.into_error()))?;
+        write_header(&mut file, &self.header)?;
+        Ok(self.header.record_count)
+    }
+}
+
+/** Block index of a delta encoded file and its records per block, checked against the file by `PriceReader`. */
+pub(crate) fn read_block_index(bytes: &[u8], path: &str) -> Result<(Vec<Block>, usize), Error> {
+    read_index(bytes, path, INDEX_ENTRY_SIZE, |buf| {
+        let entry = BlockIndexEntry::from_bytes(buf)?;
+        Ok((entry.offset, entry.first_time, entry.first_price, entry.count))
+    })
+}
+
+/**
+ * Reads the footer and the index entries before it, `parse` gives offset, first time, first price and count of an entry.
+ * Each block ends where the next one starts, the last one at the index.
+ */
+pub(crate) fn read_index<P>(bytes: &[u8], path: &str, entry_size: usize, parse: P) -> Result<(Vec<Block>, usize), Error>
+where
+    P: Fn(&[u8]) -> Result<(u64, u32, u64, u32), Error>,
+{
+    let (index_bytes, block_records) = read_footer(bytes, path, entry_size)?;
+    let index_offset = bytes.len() - FOOTER_SIZE - index_bytes.len();
+
+    let mut blocks = Vec::with_capacity(index_bytes.len() / entry_size);
+    for buf in index_bytes.chunks(entry_size) {
+        let (offset, first_time, first_price, count) = parse(buf)?;
+        blocks.push(Block { offset: offset as usize, end: index_offset, first_time, first_price, count: count as usize });
+    }
+    for at in 1..blocks.len() {
+        blocks[at - 1].end = blocks[at].offset;
+    }
+    Ok((blocks, block_records as usize))
+}
+
+/** Decodes one block into the raw close records it stands for. */
+pub(crate) fn decode_block(mut bytes: &[u8], block: &Block, width: PriceWidth) -> Result<Vec<u8>, Error> {
+    let corrupt = || Error::Format(format!("Block at {} is cut short or corrupt", block.offset));
+    let mut record = SolanaPriceEntry { time: block.first_time, close_price: block.first_price };
+
+    // Every record after the first takes at least two bytes, which bounds the allocation below.
+    if block.count == 0 || block.count - 1 > bytes.len() / 2 {
+        return Err(corrupt());
+    }
+    let mut records = Vec::with_capacity(block.count * (4 + width.bytes()));
+    record.write_le(&mut records, width)?;
+    for _ in 1..block.count {
+        let (time_delta, used) = read_varint(bytes).ok_or_else(corrupt)?;
+        bytes = &bytes[used..];
+        let (price_delta, used) = read_varint(bytes).ok_or_else(corrupt)?;
+        bytes = &bytes[used..];
+
+        record.time = u32::try_from(record.time as u64 + time_delta).map_err(|_| corrupt())?;
+        record.close_price = record.close_price.wrapping_add(unzigzag(price_delta));
+        record.write_le(&mut records, width)?;
+    }
+    Ok(records)
+}
+
+/** Converts a raw close `.dat` file into a delta encoded one, returns the number of records. */
+pub fn encode_delta(source: &str, dest: &str, block_records: u32) -> Result<u64, Error> {
+    let reader = PriceReader::open(source)?;
+    let mut entries = reader.records::<SolanaPriceEntry>(0, reader.len())?;
+
+    let temp_file = temp_path(dest);
+    let mut writer = DeltaWriter::create(&temp_file, &reader.raw_header(), block_records)?;
+    loop {
+        let chunk = entries.by_ref().take(CHUNK_RECORDS).collect::<Result<Vec<_>, Error>>()?;
+        if chunk.is_empty() {
+            break;
+        }
+        writer.write(&chunk)?;
+    }
+    let records = writer.finish()?;
+    commit_temp(&temp_file, dest)?;
+    Ok(records)
+}
+
+/** Converts a delta encoded file back into raw close records, with the header it was encoded with. */
+pub fn decode_delta(source: &str, dest: &str) -> Result<u64, Error> {
+    decode_to_raw(source, dest, Encoding::Delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prep::atomic::test_path;
+    use crate::prep::merge::{write_to_file, OverlapPolicy};
+
+    // One second steps with a gap every seventh record, prices jumping both ways by up to the full u64 range.
+    fn entries(count: usize) -> Vec<SolanaPriceEntry> {
+        let prices = [0, 1, u64::MAX, 0, u64::MAX / 2, u64::MAX - 1, 2_850, 2_849, 1 << 63];
+        let mut time = 1_700_000_000;
+        (0..count)
+            .map(|index| {
+                time += if index % 7 == 6 { 3_600 } else { 1 };
+                SolanaPriceEntry { time, close_price: prices[index % prices.len()] }
+            })
+            .collect()
+    }
+
+    fn u64_header() -> FileHeader {
+        FileHeader { price_width: PriceWidth::U64, ..FileHeader::default() }
+    }
+
+    fn write_delta(path: &str, data: &[SolanaPriceEntry], block_records: u32) -> u64 {
+        let mut writer = DeltaWriter::create(path, &u64_header(), block_records).unwrap();
+        writer.write(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        let values = [0, 1, 127, 128, 255, 16_383, 16_384, u32::MAX as u64, u64::MAX - 1, u64::MAX];
+        for value in values.into_iter().chain((0..64).map(|shift| 1u64 << shift)) {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            assert_eq!(out.len(), (64 - value.leading_zeros() as usize).div_ceil(7).max(1));
+            assert_eq!(read_varint(&out), Some((value, out.len())), "{}", value);
+
+            // Cut short, the last byte still has its continuation bit set.
+            assert_eq!(read_varint(&out[..out.len() - 1]), None);
+        }
+        assert_eq!(read_varint(&[0x80; 11]), None);
+    }
+
+    #[test]
+    fn zigzag_round_trip() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1i64 as u64), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(-2i64 as u64), 3);
+        assert_eq!(zigzag(i64::MAX as u64), u64::MAX - 1);
+        assert_eq!(zigzag(i64::MIN as u64), u64::MAX);
+
+        for delta in [0, 1, 2, u64::MAX, u64::MAX - 1, 1 << 63, (1 << 63) - 1, (1 << 63) + 1] {
+            assert_eq!(unzigzag(zigzag(delta)), delta);
+        }
+        // Wrapping deltas between the extremes come back exactly.
+        for (from, to) in [(0, u64::MAX), (u64::MAX, 0), (1, 1 << 63), (1 << 63, 0)] {
+            assert_eq!(from.wrapping_add(unzigzag(zigzag(to.wrapping_sub(from)))), to);
+        }
+    }
+
+    #[test]
+    fn round_trip_at_block_boundaries() {
+        let block_records = 4;
+        for count in [0, 1, 3, 4, 5, 8, 9, 23] {
+            let path = test_path(&format!("delta-boundary-{}", count));
+            let data = entries(count);
+            assert_eq!(write_delta(&path, &data, block_records), count as u64);
+
+            let reader = PriceReader::open(&path).unwrap();
+            assert_eq!(reader.encoding(), Encoding::Delta);
+            assert_eq!(reader.len(), count);
+            assert_eq!(reader.block_count(), count.div_ceil(block_records as usize));
+            let read: Vec<_> = reader.iter().collect::<Result<_, _>>().unwrap();
+            assert_eq!(read, data);
+
+            for (index, entry) in data.iter().enumerate() {
+                assert_eq!(reader.get(index).unwrap(), Some(*entry));
+                assert_eq!(reader.lower_bound(entry.time).unwrap(), index);
+                assert_eq!(reader.lower_bound(entry.time + 1).unwrap(), index + 1);
+                assert_eq!(reader.price_at(entry.time).unwrap(), Some(entry.close_price));
+            }
+            assert_eq!(reader.get(count).unwrap(), None);
+            assert_eq!(reader.lower_bound(0).unwrap(), 0);
+
+            let header = reader.header().unwrap();
+            assert_eq!(header.record_count, count as u64);
+            assert_eq!(header.first_time, data.first().map_or(0, |entry| entry.time));
+            assert_eq!(header.last_time, data.last().map_or(0, |entry| entry.time));
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn chunked_writes_match_one_write() {
+        let data = entries(19);
+        let whole = test_path("delta-whole");
+        let chunked = test_path("delta-chunked");
+        write_delta(&whole, &data, 4);
+
+        // Chunks ending before, on and after block boundaries.
+        let mut writer = DeltaWriter::create(&chunked, &u64_header(), 4).unwrap();
+        for chunk in [&data[..3], &data[3..4], &data[4..9], &[], &data[9..]] {
+            writer.write(chunk).unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(std::fs::read(&whole).unwrap(), std::fs::read(&chunked).unwrap());
+        std::fs::remove_file(&whole).unwrap();
+        std::fs::remove_file(&chunked).unwrap();
+    }
+
+    #[test]
+    fn encode_and_decode_give_back_the_raw_file() {
+        let raw = test_path("delta-raw");
+        let encoded = test_path("delta-encoded");
+        let decoded = test_path("delta-decoded");
+        let data = entries(10_000);
+        let format = u64_header().format();
+        write_to_file(&raw, &data, format, OverlapPolicy::Error).unwrap();
+
+        assert_eq!(encode_delta(&raw, &encoded, 1_000).unwrap(), 10_000);
+        assert_eq!(PriceReader::open(&encoded).unwrap().block_count(), 10);
+        assert_eq!(decode_delta(&encoded, &decoded).unwrap(), 10_000);
+        assert_eq!(std::fs::read(&raw).unwrap(), std::fs::read(&decoded).unwrap());
+
+        for path in [raw, encoded, decoded] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn out_of_order_and_oversized_records_are_refused() {
+        let path = test_path("delta-refused");
+        let mut writer = DeltaWriter::create(&path, &u64_header(), 4).unwrap();
+        let data = entries(2);
+        writer.write(&data).unwrap();
+        assert!(matches!(writer.write(&data[1..]), Err(Error::Timeline { .. })));
+
+        let mut writer = DeltaWriter::create(&path, &FileHeader::default(), 4).unwrap();
+        let too_big = SolanaPriceEntry { time: 1, close_price: u32::MAX as u64 + 1 };
+        assert!(matches!(writer.write(&[too_big]), Err(Error::Format(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cut_short_block_is_an_error() {
+        let block = Block { offset: HEADER_SIZE, end: HEADER_SIZE + 3, first_time: 10, first_price: 5, count: 3 };
+        // Second record whole, the third's price delta missing.
+        assert!(decode_block(&[1, 2, 1], &block, PriceWidth::U32).is_err());
+        assert_eq!(decode_block(&[1, 2, 1, 1], &block, PriceWidth::U32).unwrap().len(), 3 * 8);
+
+        let huge = Block { count: usize::MAX, ..block };
+        assert!(decode_block(&[1, 2, 1, 1], &huge, PriceWidth::U32).is_err());
+    }
+
+    #[test]
+    fn corrupt_footer_is_an_error() {
+        let path = test_path("delta-footer");
+        write_delta(&path, &entries(100), 16);
+        let original = std::fs::read(&path).unwrap();
+        let footer = original.len() - FOOTER_SIZE;
+
+        for (at, value) in [(footer, u64::MAX), (footer, 1), (footer, HEADER_SIZE as u64 + 1)] {
+            let mut bytes = original.clone();
+            bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+            std::fs::write(&path, &bytes).unwrap();
+            assert!(matches!(PriceReader::open(&path), Err(Error::Format(_))), "index offset {}", value);
+        }
+
+        let mut bytes = original.clone();
+        bytes[footer + 12..footer + 16].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(PriceReader::open(&path), Err(Error::Format(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+};
+
+use crate::prep::atomic::{commit_temp, temp_path};
+use crate::prep::error::Error;
+use crate::prep::header::{write_header, FillPolicy, RecordLayout};
+use crate::prep::reader::PriceReader;
+use crate::prep::record::{OhlcvEntry, Record, SolanaPriceEntry};
+
+/** Side file next to a densified output, one bit per record (LSB first), set where the record was synthesized. */
+pub fn bitmap_path(dest: &str) -> String {
+    format!("{}.filled", dest)
+}
+
+/** Which records of a densified file were synthesized, see `bitmap_path`. */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FillBitmap {
+    bits: Vec<u8>,
+    len: usize,
+}
+
+impl FillBitmap {
+    pub fn load(dest: &str) -> Result<FillBitmap, Error> {
+        let bits = fs::read(bitmap_path(dest))?;
+        let len = bits.len() * 8;
+        Ok(FillBitmap { bits, len })
+    }
+
+    fn push(&mut self, filled: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bits.push(0);
+        }
+        if filled {
+            self.bits[self.len / 8] |= 1 << (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /** Whether the record at `index` was synthesized, `false` past the end. */
+    pub fn is_filled(&self, index: usize) -> bool {
+        index < self.len && self.bits[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    pub fn filled_count(&self) -> usize {
+        self.bits.iter().map(|byte| byte.count_ones() as usize).sum()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+}
+
+/**
+ * Writes `source` to `dest` as a contiguous series, one record per interval from its first to its last record.
+ * Missing records are synthesized with `fill` and marked in the bitmap at `bitmap_path(dest)`.
+ */
+pub fn densify(source: &str, dest: &str, fill: FillPolicy) -> Result<FillBitmap, Error> {
+    let reader = PriceReader::open(source)?;
+
+    match reader.layout() {
+        RecordLayout::Close => densify_records::<SolanaPriceEntry>(source, &reader, dest, fill),
+        RecordLayout::Ohlcv => densify_records::<OhlcvEntry>(source, &reader, dest, fill),
+    }
+}
+
+fn densify_records<R: Record>(source: &str, reader: &PriceReader, dest: &str, fill: FillPolicy) -> Result<FillBitmap, Error> {
+    // Symbol, interval and price format carry over, legacy sources get the defaults. Encoded sources come out raw.
+    let mut header = reader.raw_header();
+    header.fill = Some(fill);
+    let interval = header.interval_secs.max(1);
+    let width = header.price_width;
+
+    let temp_file = temp_path(dest);
+    let mut file = File::create(&temp_file)?;
+    write_header(&mut file, &header)?; // Rewritten with the counts once the records are in.
+    let mut writer = BufWriter::new(&file);
+    let mut bitmap = FillBitmap::default();
+    let mut count: u64 = 0;
+    let mut previous: Option<R> = None;
+
+    for record in reader.records::<R>(0, reader.len())? {
+        let record = record?;
+
+        if let Some(previous) = previous {
+            if record.time() <= previous.time() {
+                return Err(Error::Timeline { path: source.to_string(), last: previous.time(), next: record.time() });
+            }
+
+            let mut time = previous.time() as u64 + interval as u64;
+            while time < record.time() as u64 {
+                let price = match fill {
+                    FillPolicy::ForwardFill => previous.close(),
+                    FillPolicy::Linear => interpolate(&previous, &record, time as u32),
+                    FillPolicy::Sentinel => width.max_value(),
+                };
+                R::filled(time as u32, price).write_le(&mut writer, width)?;
+                bitmap.push(true);
+                count += 1;
+                time += interval as u64;
+            }
+        }
+
+        record.write_le(&mut writer, width)?;
+        bitmap.push(false);
+        count += 1;
+        previous = Some(record);
+    }
+    writer.flush()?;
+    drop(writer);
+
+    header.record_count = count;
+    header.first_time = reader.first()?.map_or(0, |entry| entry.time);
+    header.last_time = previous.map_or(0, |record| record.time());
+    write_header(&mut file, &header)?;
+    drop(file);
+
+    // The bitmap goes first, so a committed output never comes with an older bitmap.
+    let bitmap_file = bitmap_path(dest);
+    let bitmap_temp = temp_path(&bitmap_file);
+    fs::write(&bitmap_temp, bitmap.as_bytes())?;
+    commit_temp(&bitmap_temp, &bitmap_file)?;
+    commit_temp(&temp_file, dest)?;
+
+    println!(
+        "Densified {} records into {}, {} synthesized ({:?})",
+        reader.len(), count, bitmap.filled_count(), fill
+    );
+    Ok(bitmap)
+}
+
+// Close on the straight line between the two records, rounded to the nearest unit.
+fn interpolate<R: Record>(before: &R, after: &R, time: u32) -> u64 {
+    let (t0, t1) = (before.time() as i128, after.time() as i128);
+    let (p0, p1) = (before.close() as i128, after.close() as i128);
+    let numerator = (p1 - p0) * (time as i128 - t0);
+    let span = t1 - t0;
+    let step = (2 * numerator + numerator.signum() * span) / (2 * span);
+    (p0 + step) as u64
+}
@@ -0,0 +1,121 @@
+use std::{array::TryFromSliceError, fmt, io};
+
+use arrow_schema::ArrowError;
+use parquet::errors::ParquetError;
+use zip::result::ZipError;
+
+use crate::prep::decimal::format_time;
+use crate::prep::kline::{FieldError, FieldErrorKind, KLINE_COLUMNS};
+
+/** Everything the `prep` functions can fail with, match on it to tell failure kinds apart. */
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Csv(csv::Error),
+    /** A kline value that couldn't be parsed. `file` is the CSV, or `archive.zip/entry.csv` inside an archive. */
+    Parse {
+        file: String,
+        row: u64,   // Line in the CSV, 1 based.
+        col: usize, // 0 based, see `KLINE_COLUMNS`.
+        value: String,
+        kind: FieldErrorKind,
+    },
+    /** Records that don't come after the ones before them, `next <= last`. */
+    Timeline { path: String, last: u32, next: u32 },
+    /** A source file whose SHA-256 doesn't match its `.CHECKSUM` sidecar. */
+    Checksum { file: String, expected: String, actual: String },
+    /** A file, header or option that doesn't fit: bad header, layout or price format mismatch, torn records, ... */
+    Format(String),
+}
+
+impl Error {
+    pub(crate) fn parse(file: &str, error: FieldError) -> Error {
+        Error::Parse {
+            file: file.to_string(),
+            row: error.row,
+            col: error.column,
+            value: error.value,
+            kind: error.kind,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Csv(err) => write!(f, "{}", err),
+            Error::Parse { file, row, col, value, kind } => {
+                let name = KLINE_COLUMNS.get(*col).unwrap_or(&"?");
+                write!(f, "{}: row {}, column {} ({}), value {:?}: {}", file, row, col, name, value, kind)
+            }
+            Error::Timeline { path, last, next } => write!(
+                f,
+                "Timeline validation failed for {}:\n  Ends at:     {} ({})\n  Next starts: {} ({})\nRecords have to start after the last timestamp.",
+                path, last, format_time(*last), next, format_time(*next)
+            ),
+            Error::Checksum { file, expected, actual } => {
+                write!(f, "Checksum mismatch for {}: expected {}, got {}", file, expected, actual)
+            }
+            Error::Format(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Csv(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Error {
+        Error::Csv(err)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(err: ZipError) -> Error {
+        match err {
+            ZipError::Io(err) => Error::Io(err),
+            other => Error::Format(format!("Bad zip archive: {}", other)),
+        }
+    }
+}
+
+impl From<ArrowError> for Error {
+    fn from(err: ArrowError) -> Error {
+        match err {
+            ArrowError::IoError(_, err) => Error::Io(err),
+            other => Error::Format(format!("Arrow: {}", other)),
+        }
+    }
+}
+
+impl From<ParquetError> for Error {
+    fn from(err: ParquetError) -> Error {
+        Error::Format(format!("Parquet: {}", err))
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Format(format!("SQLite: {}", err))
+    }
+}
+
+impl From<TryFromSliceError> for Error {
+    fn from(err: TryFromSliceError) -> Error {
+        Error::Format(err.to_string())
+    }
+}
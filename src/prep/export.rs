@@ -0,0 +1,182 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+use arrow_array::{ArrayRef, Decimal128Array, RecordBatch, TimestampMillisecondArray, UInt32Array};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::DateTime;
+use parquet::arrow::ArrowWriter;
+
+use crate::prep::atomic::{commit_temp, temp_path};
+use crate::prep::decimal::format_fixed;
+use crate::prep::error::Error;
+use crate::prep::header::DEFAULT_PRICE_SCALE;
+use crate::prep::reader::{Entries, PriceReader};
+
+// Rows per Parquet record batch.
+const BATCH_ROWS: usize = 1 << 16;
+
+// Digits of the largest u64 price, the precision of the Parquet close column.
+pub(crate) const PRICE_PRECISION: u8 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    JsonLines,
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ExportFormat, String> {
+        match value {
+            "csv" => Ok(ExportFormat::Csv),
+            "jsonl" => Ok(ExportFormat::JsonLines),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!("Unknown export format {:?}, use csv, jsonl or parquet", value)),
+        }
+    }
+}
+
+impl ExportFormat {
+    /** Format for an output path by its extension, `.jsonl`/`.json` or `.parquet`, CSV otherwise. */
+    pub fn for_path(path: &str) -> ExportFormat {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") | Some("json") => ExportFormat::JsonLines,
+            Some("parquet") => ExportFormat::Parquet,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+/** How exported timestamps are written. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeFormat {
+    #[default]
+    Epoch, // Seconds since epoch, a `UInt32` column in Parquet.
+    Iso,   // `2024-01-31T12:00:00Z`, a UTC timestamp column in Parquet, in milliseconds since Parquet has no seconds unit.
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<TimeFormat, String> {
+        match value {
+            "epoch" => Ok(TimeFormat::Epoch),
+            "iso" => Ok(TimeFormat::Iso),
+            _ => Err(format!("Unknown time format {:?}, use epoch or iso", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub time: TimeFormat,
+    pub from: u32, // Only records with `from <= time < to` are exported.
+    pub to: u32,
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions { format: ExportFormat::default(), time: TimeFormat::default(), from: 0, to: u32::MAX }
+    }
+}
+
+fn iso_time(time: u32) -> String {
+    DateTime::from_timestamp(time as i64, 0)
+        .map(|dt| dt.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .unwrap_or_else(|| time.to_string())
+}
+
+/**
+ * Writes the time and close of every record in the window to `dest`, prices with the decimals of the file header.
+ * Records are streamed from the memory-mapped file, the output is written through a temp file and renamed at the end.
+ * Returns the number of records exported.
+ */
+pub fn export(source: &str, dest: &str, options: &ExportOptions) -> Result<u64, Error> {
+    let reader = PriceReader::open(source)?;
+    let scale = reader.header().map_or(DEFAULT_PRICE_SCALE, |header| header.price_scale);
+    let entries = reader.range(options.from, options.to)?;
+    let count = entries.len() as u64;
+
+    let temp_file = temp_path(dest);
+    let file = File::create(&temp_file)?;
+    match options.format {
+        ExportFormat::Csv => write_csv(file, entries, scale, options.time)?,
+        ExportFormat::JsonLines => write_json_lines(file, entries, scale, options.time)?,
+        ExportFormat::Parquet => write_parquet(file, entries, scale, options.time)?,
+    }
+    commit_temp(&temp_file, dest)?;
+
+    Ok(count)
+}
+
+fn write_csv(file: File, entries: Entries, scale: u8, time: TimeFormat) -> Result<(), Error> {
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "time,close")?;
+    for entry in entries {
+        let entry = entry?;
+        let close = format_fixed(entry.close_price, scale);
+        match time {
+            TimeFormat::Epoch => writeln!(writer, "{},{}", entry.time, close)?,
+            TimeFormat::Iso => writeln!(writer, "{},{}", iso_time(entry.time), close)?,
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Prices are written as JSON numbers with the exact decimals, not through a float.
+fn write_json_lines(file: File, entries: Entries, scale: u8, time: TimeFormat) -> Result<(), Error> {
+    let mut writer = BufWriter::new(file);
+    for entry in entries {
+        let entry = entry?;
+        let close = format_fixed(entry.close_price, scale);
+        match time {
+            TimeFormat::Epoch => writeln!(writer, "{{\"time\":{},\"close\":{}}}", entry.time, close)?,
+            TimeFormat::Iso => writeln!(writer, "{{\"time\":\"{}\",\"close\":{}}}", iso_time(entry.time), close)?,
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_parquet(file: File, entries: Entries, scale: u8, time: TimeFormat) -> Result<(), Error> {
+    let time_type = match time {
+        TimeFormat::Epoch => DataType::UInt32,
+        TimeFormat::Iso => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+    };
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("time", time_type, false),
+        Field::new("close", DataType::Decimal128(PRICE_PRECISION, scale as i8), false),
+    ]));
+
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+    let mut entries = entries.peekable();
+
+    while entries.peek().is_some() {
+        let batch = entries.by_ref().take(BATCH_ROWS).collect::<Result<Vec<_>, Error>>()?;
+
+        let time_column: ArrayRef = match time {
+            TimeFormat::Epoch => Arc::new(UInt32Array::from_iter_values(batch.iter().map(|entry| entry.time))),
+            TimeFormat::Iso => Arc::new(
+                TimestampMillisecondArray::from_iter_values(batch.iter().map(|entry| entry.time as i64 * 1000)).with_timezone("UTC"),
+            ),
+        };
+        let close_column: ArrayRef = Arc::new(
+            Decimal128Array::from_iter_values(batch.iter().map(|entry| entry.close_price as i128))
+                .with_precision_and_scale(PRICE_PRECISION, scale as i8)?,
+        );
+        writer.write(&RecordBatch::try_new(schema.clone(), vec![time_column, close_column])?)?;
+    }
+
+    writer.close()?;
+    Ok(())
+}
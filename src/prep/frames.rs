@@ -0,0 +1,313 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use crate::prep::atomic::{commit_temp, temp_path};
+use crate::prep::delta::{read_index, write_footer};
+use crate::prep::error::Error;
+use crate::prep::header::{write_header, Encoding, FileHeader, RecordLayout, HEADER_SIZE};
+use crate::prep::reader::{decode_to_raw, Block, PriceReader, Records};
+use crate::prep::record::{OhlcvEntry, Record, SolanaPriceEntry};
+
+// Layout of a zstd framed file, all integers little-endian:
+//
+//  header     64 bytes, `Encoding::Zstd`, record count and time span filled in
+//  frames     one zstd frame per `frame_records` raw records, each decodable on its own
+//  index      per frame: byte offset (u64), first time (u32), record count (u32)
+//  footer     index offset (u64), records per frame (u32), frame count (u32), as for delta files
+//
+// A frame decompresses to exactly the fixed-width records a raw file would hold, in any layout.
+
+pub const DEFAULT_FRAME_RECORDS: u32 = 1 << 16;
+pub const DEFAULT_ZSTD_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+const INDEX_ENTRY_SIZE: usize = 16;
+
+/** Where a frame starts and the first timestamp in it. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FrameIndexEntry {
+    offset: u64,
+    first_time: u32,
+    count: u32,
+}
+
+impl FrameIndexEntry {
+    fn to_bytes(self) -> [u8; INDEX_ENTRY_SIZE] {
+        let mut buf = [0u8; INDEX_ENTRY_SIZE];
+        buf[0..8].copy_from_slice(&self.offset.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.first_time.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.count.to_le_bytes());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Result<FrameIndexEntry, Error> {
+        Ok(FrameIndexEntry {
+            offset: u64::from_le_bytes(buf[0..8].try_into()?),
+            first_time: u32::from_le_bytes(buf[8..12].try_into()?),
+            count: u32::from_le_bytes(buf[12..16].try_into()?),
+        })
+    }
+}
+
+/**
+ * Streams sorted records into a zstd framed file, compressing every `frame_records` of them into their own frame.
+ * `finish` writes the last frame, the index and footer and fills in the header.
+ */
+pub struct FrameWriter {
+    path: String,
+    file: BufWriter<File>,
+    header: FileHeader,
+    frame_records: u32,
+    level: i32,
+    index: Vec<FrameIndexEntry>,
+    frame: Vec<u8>,
+    offset: u64,
+}
+
+impl FrameWriter {
+    /** Takes layout, symbol, interval and price format from `header`. */
+    pub fn create(path: &str, header: &FileHeader, frame_records: u32, level: i32) -> Result<FrameWriter, Error> {
+        if frame_records == 0 {
+            return Err(Error::Format("Frames need at least one record".to_string()));
+        }
+
+        let header = FileHeader { encoding: Encoding::Zstd, record_count: 0, first_time: 0, last_time: 0, ..header.clone() };
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&header.to_bytes()?)?;
+
+        Ok(FrameWriter {
+            path: path.to_string(),
+            file,
+            frame: Vec::with_capacity(frame_records as usize * header.record_size()),
+            header,
+            frame_records,
+            level,
+            index: Vec::new(),
+            offset: HEADER_SIZE as u64,
+        })
+    }
+
+    pub fn write<R: Record>(&mut self, entries: &[R]) -> Result<(), Error> {
+        if R::LAYOUT != self.header.layout {
+            return Err(Error::Format(format!("{} holds {:?} records, can't write {:?} records", self.path, self.header.layout, R::LAYOUT)));
+        }
+
+        for entry in entries {
+            if entry.max_price() > self.header.price_width.max_value() {
+                return Err(Error::Format(format!(
+                    "Price {} at {} doesn't fit {:?} storage",
+                    entry.max_price(), entry.time(), self.header.price_width
+                )));
+            }
+            if self.header.record_count > 0 && entry.time() <= self.header.last_time {
+                return Err(Error::Timeline { path: self.path.clone(), last: self.header.last_time, next: entry.time() });
+            }
+
+            if self.index.last().is_none_or(|frame| frame.count == self.frame_records) {
+                self.flush_frame()?;
+                self.index.push(FrameIndexEntry { offset: self.offset, first_time: entry.time(), count: 0 });
+            }
+            entry.write_le(&mut self.frame, self.header.price_width)?;
+            if let Some(frame) = self.index.last_mut() {
+                frame.count += 1;
+            }
+
+            if self.header.record_count == 0 {
+                self.header.first_time = entry.time();
+            }
+            self.header.record_count += 1;
+            self.header.last_time = entry.time();
+        }
+        Ok(())
+    }
+
+    fn flush_frame(&mut self) -> Result<(), Error> {
+        if self.frame.is_empty() {
+            return Ok(());
+        }
+        let compressed = zstd::bulk::compress(&self.frame, self.level)?;
+        self.file.write_all(&compressed)?;
+        self.offset += compressed.len() as u64;
+        self.frame.clear();
+        Ok(())
+    }
+
+    /** Writes the last frame, index and footer, returns the number of records written. */
+    pub fn finish(mut self) -> Result<u64, Error> {
+        self.flush_frame()?;
+
+        let index_offset = self.offset;
+        for frame in &self.index {
+            self.file.write_all(&frame.to_bytes())?;
+        }
+        write_footer(&mut self.file, index_offset, self.frame_records, self.index.len())?;
+
+        let mut file = self.file.into_inner().map_err(|err| Error::Io(err.into_error()))?;
+        write_header(&mut file, &self.header)?;
+        Ok(self.header.record_count)
+    }
+}
+
+/** Frame index of a zstd framed file and its records per frame, checked against the file by `PriceReader`. */
+pub(crate) fn read_frame_index(bytes: &[u8], path: &str) -> Result<(Vec<Block>, usize), Error> {
+    read_index(bytes, path, INDEX_ENTRY_SIZE, |buf| {
+        let entry = FrameIndexEntry::from_bytes(buf)?;
+        Ok((entry.offset, entry.first_time, 0, entry.count))
+    })
+}
+
+/** Decompresses one frame into its raw records, refusing counts beyond the `records` the file holds. */
+pub(crate) fn decompress_frame(bytes: &[u8], frame: &Block, record_size: usize, records: usize) -> Result<Vec<u8>, Error> {
+    // The count comes from the index, check it before it sizes the output buffer.
+    let capacity = Some(frame.count).filter(|&count| count <= records).and_then(|count| count.checked_mul(record_size));
+    let capacity = capacity.ok_or_else(|| {
+        Error::Format(format!("Frame at {} claims {} records, the file holds {}", frame.offset, frame.count, records))
+    })?;
+    Ok(zstd::bulk::decompress(bytes, capacity)?)
+}
+
+/** Compresses a raw `.dat` file of either layout into zstd frames, returns the number of records. */
+pub fn compress_frames(source: &str, dest: &str, frame_records: u32, level: i32) -> Result<u64, Error> {
+    let reader = PriceReader::open(source)?;
+
+    let temp_file = temp_path(dest);
+    let mut writer = FrameWriter::create(&temp_file, &reader.raw_header(), frame_records, level)?;
+    match reader.layout() {
+        RecordLayout::Close => write_frames(reader.records::<SolanaPriceEntry>(0, reader.len())?, &mut writer, frame_records)?,
+        RecordLayout::Ohlcv => write_frames(reader.records::<OhlcvEntry>(0, reader.len())?, &mut writer, frame_records)?,
+    }
+    let records = writer.finish()?;
+    commit_temp(&temp_file, dest)?;
+    Ok(records)
+}
+
+fn write_frames<R: Record>(mut records: Records<'_, R>, writer: &mut FrameWriter, frame_records: u32) -> Result<(), Error> {
+    loop {
+        let chunk = records.by_ref().take(frame_records as usize).collect::<Result<Vec<_>, Error>>()?;
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        writer.write(&chunk)?;
+    }
+}
+
+/** Decompresses a zstd framed file back into raw records, with the header it was compressed with. */
+pub fn decompress_frames(source: &str, dest: &str) -> Result<u64, Error> {
+    decode_to_raw(source, dest, Encoding::Zstd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prep::atomic::test_path;
+    use crate::prep::header::PriceWidth;
+    use crate::prep::merge::{write_records, OverlapPolicy};
+
+    fn candles(count: usize) -> Vec<OhlcvEntry> {
+        (0..count as u64)
+            .map(|index| OhlcvEntry {
+                time: 1_700_000_000 + index as u32 * 60,
+                open: 2_850 + index,
+                high: 2_900 + index,
+                low: 2_800 + index,
+                close: 2_860 + index,
+                volume: index * 100_000_000,
+                quote_volume: index * 285_000_000_000,
+                trades: index as u32,
+                taker_buy_base_volume: index,
+                taker_buy_quote_volume: index * 3,
+            })
+            .collect()
+    }
+
+    fn header(layout: RecordLayout) -> FileHeader {
+        FileHeader { layout, price_width: PriceWidth::U64, ..FileHeader::default() }
+    }
+
+    #[test]
+    fn round_trip_at_frame_boundaries() {
+        let frame_records = 3;
+        for count in [0, 1, 2, 3, 4, 6, 7, 20] {
+            let path = test_path(&format!("frames-boundary-{}", count));
+            let data = candles(count);
+            let mut writer = FrameWriter::create(&path, &header(RecordLayout::Ohlcv), frame_records, DEFAULT_ZSTD_LEVEL).unwrap();
+            writer.write(&data).unwrap();
+            assert_eq!(writer.finish().unwrap(), count as u64);
+
+            let reader = PriceReader::open(&path).unwrap();
+            assert_eq!(reader.encoding(), Encoding::Zstd);
+            assert_eq!(reader.len(), count);
+            assert_eq!(reader.block_count(), count.div_ceil(frame_records as usize));
+            let read: Vec<_> = reader.records::<OhlcvEntry>(0, count).unwrap().collect::<Result<_, _>>().unwrap();
+            assert_eq!(read, data);
+
+            for (index, candle) in data.iter().enumerate() {
+                assert_eq!(reader.get_ohlcv(index).unwrap(), Some(*candle));
+                assert_eq!(reader.get(index).unwrap(), Some(SolanaPriceEntry::from(*candle)));
+                assert_eq!(reader.lower_bound(candle.time).unwrap(), index);
+                assert_eq!(reader.lower_bound(candle.time + 1).unwrap(), index + 1);
+            }
+            // A window across a frame boundary decompresses both frames.
+            if count >= 5 {
+                let window: Vec<_> = reader.range(data[1].time, data[5].time).unwrap().collect::<Result<_, _>>().unwrap();
+                assert_eq!(window, data[1..5].iter().map(|&candle| SolanaPriceEntry::from(candle)).collect::<Vec<_>>());
+            }
+            assert_eq!(reader.get_ohlcv(count).unwrap(), None);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn compress_and_decompress_give_back_the_raw_file() {
+        for (layout, frame_records) in [(RecordLayout::Close, 1), (RecordLayout::Close, 1_000), (RecordLayout::Ohlcv, 7)] {
+            let raw = test_path(&format!("frames-raw-{:?}-{}", layout, frame_records));
+            let compressed = test_path(&format!("frames-compressed-{:?}-{}", layout, frame_records));
+            let decompressed = test_path(&format!("frames-decompressed-{:?}-{}", layout, frame_records));
+            let data = candles(2_500);
+            let format = header(layout).format();
+            match layout {
+                RecordLayout::Close => {
+                    let closes: Vec<SolanaPriceEntry> = data.iter().map(|&candle| candle.into()).collect();
+                    write_records(&raw, &closes, format, OverlapPolicy::Error).unwrap();
+                }
+                RecordLayout::Ohlcv => {
+                    write_records(&raw, &data, format, OverlapPolicy::Error).unwrap();
+                }
+            }
+
+            assert_eq!(compress_frames(&raw, &compressed, frame_records, DEFAULT_ZSTD_LEVEL).unwrap(), 2_500);
+            assert_eq!(PriceReader::open(&compressed).unwrap().block_count(), 2_500usize.div_ceil(frame_records as usize));
+            assert_eq!(decompress_frames(&compressed, &decompressed).unwrap(), 2_500);
+            assert_eq!(std::fs::read(&raw).unwrap(), std::fs::read(&decompressed).unwrap());
+
+            for path in [raw, compressed, decompressed] {
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_layout_and_out_of_order_records_are_refused() {
+        let path = test_path("frames-refused");
+        let mut writer = FrameWriter::create(&path, &header(RecordLayout::Close), 4, DEFAULT_ZSTD_LEVEL).unwrap();
+        assert!(matches!(writer.write(&candles(1)), Err(Error::Format(_))));
+
+        let entry = SolanaPriceEntry { time: 10, close_price: 1 };
+        writer.write(&[entry]).unwrap();
+        assert!(matches!(writer.write(&[entry]), Err(Error::Timeline { .. })));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn frame_counts_beyond_the_file_are_refused() {
+        let raw = vec![7u8; 4 * 8];
+        let bytes = zstd::bulk::compress(&raw, DEFAULT_ZSTD_LEVEL).unwrap();
+        let frame = Block { offset: HEADER_SIZE, end: HEADER_SIZE + bytes.len(), first_time: 1, first_price: 0, count: 4 };
+        assert_eq!(decompress_frame(&bytes, &frame, 8, 4).unwrap(), raw);
+
+        assert!(matches!(decompress_frame(&bytes, &frame, 8, 3), Err(Error::Format(_))));
+        let huge = Block { count: usize::MAX, ..frame };
+        assert!(matches!(decompress_frame(&bytes, &huge, 8, usize::MAX), Err(Error::Format(_))));
+    }
+}
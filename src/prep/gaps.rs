@@ -0,0 +1,208 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Datelike, NaiveDate};
+
+use crate::prep::decimal::format_time;
+use crate::prep::error::Error;
+use crate::prep::header::DEFAULT_INTERVAL_SECS;
+use crate::prep::reader::PriceReader;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/** Missing stretch of the series, `start` is the first missing second and `end` the next timestamp present. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Gap {
+    /** Missing seconds, `end - start`. */
+    pub fn duration(&self) -> u32 {
+        self.end - self.start
+    }
+}
+
+/** Bucket size of the coverage part of a `GapReport`. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoveragePeriod {
+    #[default]
+    Day,
+    Month,
+}
+
+/** Records present against records expected in one day or month. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub period: String, // "2020-08-11" or "2020-08".
+    pub present: u64,
+    pub expected: u64, // Only counts seconds between the first and last record of the file.
+}
+
+impl Coverage {
+    pub fn percent(&self) -> f64 {
+        if self.expected == 0 {
+            return 100.0;
+        }
+        self.present as f64 * 100.0 / self.expected as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GapReport {
+    pub interval_secs: u32,
+    pub longer_than_secs: u32, // Only gaps of more missing seconds than this are listed.
+    pub records: u64,
+    pub first_time: Option<u32>,
+    pub last_time: Option<u32>,
+    pub gaps: Vec<Gap>,
+    pub coverage: Vec<Coverage>,
+}
+
+/** Scans a merged `.dat` file for missing timestamps and buckets its coverage per day or month. */
+pub fn gap_report(path: &str, longer_than_secs: u32, period: CoveragePeriod) -> Result<GapReport, Error> {
+    let reader = PriceReader::open(path)?;
+    let interval_secs = reader.header().map_or(DEFAULT_INTERVAL_SECS, |header| header.interval_secs).max(1);
+
+    let mut gaps = Vec::new();
+    let mut coverage: Vec<Coverage> = Vec::new();
+    let mut bucket_end = i64::MIN;
+    let mut previous: Option<u32> = None;
+
+    for entry in reader.iter() {
+        let entry = entry?;
+        if let Some(previous) = previous {
+            let gap = Gap { start: previous.saturating_add(interval_secs), end: entry.time };
+            if gap.end > gap.start && gap.duration() > longer_than_secs {
+                gaps.push(gap);
+            }
+        }
+        previous = Some(entry.time);
+
+        // Periods without any record still get a row, at 0%.
+        while entry.time as i64 >= bucket_end {
+            let start = if coverage.is_empty() { period_start(entry.time as i64, period) } else { bucket_end };
+            bucket_end = next_period(start, period);
+            coverage.push(Coverage { period: period_name(start, period), present: 0, expected: 0 });
+        }
+        if let Some(bucket) = coverage.last_mut() {
+            bucket.present += 1;
+        }
+    }
+
+    // Expected records per bucket, clipped to the span the file covers.
+    let (first, last) = (reader.first()?, reader.last()?);
+    if let (Some(first), Some(last)) = (first, last) {
+        let span_end = last.time as i64 + interval_secs as i64;
+        let mut start = period_start(first.time as i64, period);
+        for bucket in coverage.iter_mut() {
+            let end = next_period(start, period);
+            let seconds = end.min(span_end) - start.max(first.time as i64);
+            bucket.expected = (seconds.max(0) as u64).div_ceil(interval_secs as u64);
+            start = end;
+        }
+    }
+
+    Ok(GapReport {
+        interval_secs,
+        longer_than_secs,
+        records: reader.len() as u64,
+        first_time: first.map(|entry| entry.time),
+        last_time: last.map(|entry| entry.time),
+        gaps,
+        coverage,
+    })
+}
+
+fn period_start(time: i64, period: CoveragePeriod) -> i64 {
+    let day = time - time.rem_euclid(SECONDS_PER_DAY);
+    match period {
+        CoveragePeriod::Day => day,
+        CoveragePeriod::Month => {
+            let date = DateTime::from_timestamp(day, 0).map_or(NaiveDate::MIN, |dt| dt.date_naive());
+            day - (date.day0() as i64) * SECONDS_PER_DAY
+        }
+    }
+}
+
+fn next_period(start: i64, period: CoveragePeriod) -> i64 {
+    match period {
+        CoveragePeriod::Day => start + SECONDS_PER_DAY,
+        // Any time 32 days on is in the next month.
+        CoveragePeriod::Month => period_start(start + 32 * SECONDS_PER_DAY, period),
+    }
+}
+
+fn period_name(start: i64, period: CoveragePeriod) -> String {
+    let format = match period {
+        CoveragePeriod::Day => "%Y-%m-%d",
+        CoveragePeriod::Month => "%Y-%m",
+    };
+    DateTime::from_timestamp(start, 0)
+        .map(|dt| dt.format(format).to_string())
+        .unwrap_or_else(|| start.to_string())
+}
+
+impl GapReport {
+    pub fn missing_secs(&self) -> u64 {
+        self.gaps.iter().map(|gap| gap.duration() as u64).sum()
+    }
+
+    pub fn print_table(&self) {
+        match (self.first_time, self.last_time) {
+            (Some(first), Some(last)) => println!(
+                "{} records from {} to {}, {}s interval",
+                self.records, format_time(first), format_time(last), self.interval_secs
+            ),
+            _ => println!("No records"),
+        }
+
+        println!("Gaps longer than {}s: {} ({}s missing)", self.longer_than_secs, self.gaps.len(), self.missing_secs());
+        if !self.gaps.is_empty() {
+            println!("{:<25} {:<25} {:>10}", "Start", "End", "Seconds");
+            for gap in &self.gaps {
+                println!("{:<25} {:<25} {:>10}", format_time(gap.start), format_time(gap.end), gap.duration());
+            }
+        }
+
+        println!("{:<12} {:>10} {:>10} {:>9}", "Period", "Present", "Expected", "Coverage");
+        for bucket in &self.coverage {
+            println!(
+                "{:<12} {:>10} {:>10} {:>8.3}%",
+                bucket.period, bucket.present, bucket.expected, bucket.percent()
+            );
+        }
+    }
+
+    /** Times are seconds since epoch, gaps are half-open `[start, end)`. */
+    pub fn to_json(&self) -> String {
+        let optional = |time: Option<u32>| time.map_or("null".to_string(), |time| time.to_string());
+
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"interval_secs\":{},\"longer_than_secs\":{},\"records\":{},\"first_time\":{},\"last_time\":{},\"missing_secs\":{},\"gaps\":[",
+            self.interval_secs,
+            self.longer_than_secs,
+            self.records,
+            optional(self.first_time),
+            optional(self.last_time),
+            self.missing_secs(),
+        );
+        for (index, gap) in self.gaps.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            let _ = write!(json, "{}{{\"start\":{},\"end\":{},\"duration_secs\":{}}}", separator, gap.start, gap.end, gap.duration());
+        }
+        json.push_str("],\"coverage\":[");
+        for (index, bucket) in self.coverage.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            let _ = write!(
+                json,
+                "{}{{\"period\":\"{}\",\"present\":{},\"expected\":{},\"percent\":{:.3}}}",
+                separator, bucket.period, bucket.present, bucket.expected, bucket.percent()
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
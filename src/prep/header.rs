@@ -0,0 +1,392 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    str::FromStr,
+};
+
+use crate::prep::decimal::Rounding;
+use crate::prep::error::Error;
+
+// Layout of the fixed 64 byte header, all integers little-endian:
+//
+//  0..8   magic "SOLPRICE"
+//  8..10  format version (u16)
+//  10     record layout (u8)
+//  11     price scale exponent (u8), price = value / 10^scale
+//  12..16 base interval in seconds (u32)
+//  16..32 symbol, ASCII, zero padded
+//  32..40 record count (u64)
+//  40..44 first timestamp in seconds (u32)
+//  44..48 last timestamp in seconds (u32)
+//  48     stored price width in bytes (u8), 4 or 8, version 1 files leave it 0 for 4
+//  49     fill policy of a densified file (u8), 0 for files with gaps left in
+//  50     record encoding (u8), 0 for fixed-width records
+//  51     rounding of source prices past the scale (u8), 0 truncated, 1 half-up, 2 half-even
+//  52..64 reserved, zero
+pub const MAGIC: [u8; 8] = *b"SOLPRICE";
+pub const FORMAT_VERSION: u16 = 2;
+pub const HEADER_SIZE: usize = 64;
+pub const RECORD_SIZE: usize = 8; // Close layout with u32 prices, also what legacy files hold.
+pub const SYMBOL_SIZE: usize = 16;
+
+pub const DEFAULT_SYMBOL: &str = "SOLUSDT";
+pub const DEFAULT_INTERVAL_SECS: u32 = 1;
+pub const DEFAULT_PRICE_SCALE: u8 = 3;
+pub const MAX_PRICE_SCALE: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordLayout {
+    Close = 0, // (time: u32, close_price) pairs.
+    Ohlcv = 1, // Full klines, see `OhlcvEntry`.
+}
+
+/** How many bytes each stored price takes. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PriceWidth {
+    #[default]
+    U32 = 4,
+    U64 = 8, // For scales where prices would overflow a u32, e.g. 8 decimals above $42.
+}
+
+impl PriceWidth {
+    pub fn bytes(self) -> usize {
+        self as usize
+    }
+
+    pub fn max_value(self) -> u64 {
+        match self {
+            PriceWidth::U32 => u32::MAX as u64,
+            PriceWidth::U64 => u64::MAX,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<PriceWidth> {
+        match value {
+            0 | 4 => Some(PriceWidth::U32),
+            8 => Some(PriceWidth::U64),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for PriceWidth {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<PriceWidth, String> {
+        match name {
+            "4" | "u32" => Ok(PriceWidth::U32),
+            "8" | "u64" => Ok(PriceWidth::U64),
+            _ => Err(format!("Unknown price width {:?}, use 4 or 8 bytes", name)),
+        }
+    }
+}
+
+/** How `densify` filled the seconds the source had no record for. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillPolicy {
+    ForwardFill = 1, // Last close repeated.
+    Linear = 2,      // Straight line between the closes either side of the gap.
+    Sentinel = 3,    // `PriceWidth::max_value()`, read as "no data".
+}
+
+impl FillPolicy {
+    fn from_u8(value: u8) -> Result<Option<FillPolicy>, Error> {
+        match value {
+            0 => Ok(None),
+            1 => Ok(Some(FillPolicy::ForwardFill)),
+            2 => Ok(Some(FillPolicy::Linear)),
+            3 => Ok(Some(FillPolicy::Sentinel)),
+            _ => Err(Error::Format(format!("Unknown fill policy {}", value))),
+        }
+    }
+}
+
+impl FromStr for FillPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<FillPolicy, String> {
+        match name {
+            "ffill" => Ok(FillPolicy::ForwardFill),
+            "linear" => Ok(FillPolicy::Linear),
+            "sentinel" => Ok(FillPolicy::Sentinel),
+            _ => Err(format!("Unknown fill policy {:?}, use ffill, linear or sentinel", name)),
+        }
+    }
+}
+
+/** How the records after the header are stored. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Raw = 0,   // Fixed-width records, see `RecordLayout::record_size`.
+    Delta = 1, // Blocks of delta and varint coded close records with an index, see `DeltaWriter`.
+    Zstd = 2,  // Raw records compressed in independent zstd frames with an index, see `FrameWriter`.
+}
+
+impl Encoding {
+    fn from_u8(value: u8) -> Result<Encoding, Error> {
+        match value {
+            0 => Ok(Encoding::Raw),
+            1 => Ok(Encoding::Delta),
+            2 => Ok(Encoding::Zstd),
+            _ => Err(Error::Format(format!("Unknown record encoding {}", value))),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Encoding, String> {
+        match name {
+            "raw" => Ok(Encoding::Raw),
+            "delta" => Ok(Encoding::Delta),
+            "zstd" => Ok(Encoding::Zstd),
+            _ => Err(format!("Unknown encoding {:?}, use raw, delta or zstd", name)),
+        }
+    }
+}
+
+/** Decimal places, storage width and rounding of prices, recorded in the header. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceFormat {
+    pub scale: u8, // price = value / 10^scale, 0 to 8.
+    pub width: PriceWidth,
+    pub rounding: Rounding, // How source prices with more decimals than the scale were cut down.
+}
+
+impl Default for PriceFormat {
+    fn default() -> Self {
+        PriceFormat {
+            scale: DEFAULT_PRICE_SCALE,
+            width: PriceWidth::U32,
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+impl PriceFormat {
+    /** What legacy headerless files hold, 3 decimals in a u32, truncated. */
+    pub const LEGACY: PriceFormat = PriceFormat {
+        scale: DEFAULT_PRICE_SCALE,
+        width: PriceWidth::U32,
+        rounding: Rounding::Truncate,
+    };
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.scale > MAX_PRICE_SCALE {
+            return Err(Error::Format(format!("Price scale {} is out of range, use 0 to {} decimals", self.scale, MAX_PRICE_SCALE)));
+        }
+        Ok(())
+    }
+}
+
+// Spelled like the `--scale`, `--width` and `--rounding` options.
+impl fmt::Display for PriceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} decimals in {} bytes, rounding {}", self.scale, self.width.bytes(), self.rounding)
+    }
+}
+
+impl RecordLayout {
+    fn from_u8(value: u8) -> Option<RecordLayout> {
+        match value {
+            0 => Some(RecordLayout::Close),
+            1 => Some(RecordLayout::Ohlcv),
+            _ => None,
+        }
+    }
+
+    pub fn record_size(self, width: PriceWidth) -> usize {
+        match self {
+            RecordLayout::Close => 4 + width.bytes(),
+            RecordLayout::Ohlcv => 4 + 4 * width.bytes() + 36,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u16,
+    pub layout: RecordLayout,
+    pub symbol: String,
+    pub interval_secs: u32,
+    pub price_scale: u8,
+    pub price_width: PriceWidth,
+    pub record_count: u64,
+    pub first_time: u32,
+    pub last_time: u32,
+    pub fill: Option<FillPolicy>, // Set once `densify` made the series contiguous.
+    pub encoding: Encoding,
+    pub rounding: Rounding,
+}
+
+impl Default for FileHeader {
+    fn default() -> Self {
+        FileHeader {
+            version: FORMAT_VERSION,
+            layout: RecordLayout::Close,
+            symbol: DEFAULT_SYMBOL.to_string(),
+            interval_secs: DEFAULT_INTERVAL_SECS,
+            price_scale: DEFAULT_PRICE_SCALE,
+            price_width: PriceWidth::U32,
+            record_count: 0,
+            first_time: 0,
+            last_time: 0,
+            fill: None,
+            encoding: Encoding::Raw,
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+impl FileHeader {
+    pub fn format(&self) -> PriceFormat {
+        PriceFormat {
+            scale: self.price_scale,
+            width: self.price_width,
+            rounding: self.rounding,
+        }
+    }
+
+    pub fn record_size(&self) -> usize {
+        self.layout.record_size(self.price_width)
+    }
+
+    pub fn to_bytes(&self) -> Result<[u8; HEADER_SIZE], Error> {
+        if !self.symbol.is_ascii() || self.symbol.len() > SYMBOL_SIZE {
+            return Err(Error::Format(format!("Symbol {:?} must be ASCII and at most {} bytes", self.symbol, SYMBOL_SIZE)));
+        }
+
+        let mut buf = [0u8; HEADER_SIZE];
+        buf[0..8].copy_from_slice(&MAGIC);
+        buf[8..10].copy_from_slice(&self.version.to_le_bytes());
+        buf[10] = self.layout as u8;
+        buf[11] = self.price_scale;
+        buf[12..16].copy_from_slice(&self.interval_secs.to_le_bytes());
+        buf[16..16 + self.symbol.len()].copy_from_slice(self.symbol.as_bytes());
+        buf[32..40].copy_from_slice(&self.record_count.to_le_bytes());
+        buf[40..44].copy_from_slice(&self.first_time.to_le_bytes());
+        buf[44..48].copy_from_slice(&self.last_time.to_le_bytes());
+        buf[48] = self.price_width as u8;
+        buf[49] = self.fill.map_or(0, |fill| fill as u8);
+        buf[50] = self.encoding as u8;
+        buf[51] = self.rounding as u8;
+        Ok(buf)
+    }
+
+    /** Returns `None` when the bytes don't start with the magic, i.e. a legacy headerless file. */
+    pub fn from_bytes(buf: &[u8]) -> Result<Option<FileHeader>, Error> {
+        if buf.len() < HEADER_SIZE || buf[0..8] != MAGIC {
+            return Ok(None);
+        }
+
+        let version = u16::from_le_bytes([buf[8], buf[9]]);
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(Error::Format(format!("Unsupported format version {} (this build reads up to {})", version, FORMAT_VERSION)));
+        }
+
+        let layout = RecordLayout::from_u8(buf[10])
+            .ok_or_else(|| Error::Format(format!("Unknown record layout {}", buf[10])))?;
+        let price_width = PriceWidth::from_u8(buf[48])
+            .ok_or_else(|| Error::Format(format!("Unknown price width {}", buf[48])))?;
+        let rounding = Rounding::from_u8(buf[51])
+            .ok_or_else(|| Error::Format(format!("Unknown rounding {}", buf[51])))?;
+        if buf[11] > MAX_PRICE_SCALE {
+            return Err(Error::Format(format!("Price scale {} in header is out of range", buf[11])));
+        }
+
+        let symbol_bytes = &buf[16..16 + SYMBOL_SIZE];
+        let symbol_len = symbol_bytes.iter().position(|&b| b == 0).unwrap_or(SYMBOL_SIZE);
+        let symbol = std::str::from_utf8(&symbol_bytes[..symbol_len])
+            .ok()
+            .filter(|s| s.is_ascii())
+            .ok_or_else(|| Error::Format("Symbol in header is not ASCII".to_string()))?
+            .to_string();
+
+        Ok(Some(FileHeader {
+            version,
+            layout,
+            symbol,
+            interval_secs: u32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]),
+            price_scale: buf[11],
+            price_width,
+            record_count: u64::from_le_bytes(buf[32..40].try_into()?),
+            first_time: u32::from_le_bytes(buf[40..44].try_into()?),
+            last_time: u32::from_le_bytes(buf[44..48].try_into()?),
+            fill: FillPolicy::from_u8(buf[49])?,
+            encoding: Encoding::from_u8(buf[50])?,
+            rounding,
+        }))
+    }
+
+    /** Checks the header against the actual payload length of a raw file, encoded files are refused. */
+    pub fn validate(&self, file_len: u64) -> Result<(), Error> {
+        if self.encoding != Encoding::Raw {
+            return Err(Error::Format(format!(
+                "File holds {:?} encoded records, read it with PriceReader or convert it to raw records",
+                self.encoding
+            )));
+        }
+
+        let payload = file_len.saturating_sub(HEADER_SIZE as u64);
+        let record_size = self.record_size();
+        if payload != self.record_count * record_size as u64 {
+            return Err(Error::Format(format!(
+                "Header claims {} records but file holds {} bytes of payload ({} byte records)",
+                self.record_count, payload, record_size
+            )));
+        }
+        if self.record_count > 0 && self.first_time > self.last_time {
+            return Err(Error::Format(format!(
+                "Header first timestamp {} is after last timestamp {}",
+                self.first_time, self.last_time
+            )));
+        }
+        Ok(())
+    }
+}
+
+/** Byte offset of the first record and the record size, legacy files are bare close records. */
+pub fn record_geometry(header: Option<&FileHeader>) -> (u64, u64) {
+    match header {
+        Some(header) => (HEADER_SIZE as u64, header.record_size() as u64),
+        None => (0, RECORD_SIZE as u64),
+    }
+}
+
+/** Reads the header of an opened file, leaving the cursor at the start of the records. */
+pub fn read_header(file: &mut File) -> Result<Option<FileHeader>, Error> {
+    let header = read_header_unchecked(file)?;
+    if let Some(header) = &header {
+        header.validate(file.metadata()?.len())?;
+    }
+    Ok(header)
+}
+
+/** Like `read_header` but without checking the header against the file length, for repairs. */
+pub fn read_header_unchecked(file: &mut File) -> Result<Option<FileHeader>, Error> {
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(0))?;
+
+    if len < HEADER_SIZE as u64 {
+        return Ok(None);
+    }
+
+    let mut buf = [0u8; HEADER_SIZE];
+    file.read_exact(&mut buf)?;
+
+    let header = FileHeader::from_bytes(&buf)?;
+    if header.is_none() {
+        // Legacy file, records start at byte zero.
+        file.seek(SeekFrom::Start(0))?;
+    }
+    Ok(header)
+}
+
+pub fn write_header(file: &mut File, header: &FileHeader) -> Result<(), Error> {
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header.to_bytes()?)?;
+    Ok(())
+}
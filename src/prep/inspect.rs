@@ -0,0 +1,76 @@
+use crate::prep::decimal::{format_fixed, format_time};
+use crate::prep::error::Error;
+use crate::prep::header::{RecordLayout, DEFAULT_PRICE_SCALE};
+use crate::prep::reader::PriceReader;
+use crate::prep::record::{OhlcvEntry, SolanaPriceEntry, VOLUME_SCALE};
+
+/** Part of a file `inspect` prints. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Head,
+    Middle,
+    Tail,
+    Window { from: u32, to: u32 }, // Every record with `from <= time < to`.
+}
+
+/**
+ * Prints sections of a merged `.dat` file with UTC times and decimal prices, `rows` records each for head, middle and tail.
+ * The file is memory-mapped, only the pages or blocks holding the printed records are read.
+ */
+pub fn inspect(path: &str, sections: &[Section], rows: usize) -> Result<(), Error> {
+    let reader = PriceReader::open(path)?;
+    let len = reader.len();
+    println!("{}: {} records", path, len);
+
+    for section in sections {
+        let (title, from, to) = match *section {
+            Section::Head => ("First".to_string(), 0, rows),
+            Section::Middle => {
+                let from = len.saturating_sub(rows) / 2;
+                ("Middle".to_string(), from, from + rows)
+            }
+            Section::Tail => ("Last".to_string(), len.saturating_sub(rows), len),
+            Section::Window { from, to } => (
+                format!("From {} to {}", format_time(from), format_time(to)),
+                reader.lower_bound(from)?,
+                reader.lower_bound(to)?,
+            ),
+        };
+
+        let to = to.min(len);
+        println!("\n{} {} entries:", title, to.saturating_sub(from));
+        print_records(&reader, from, to)?;
+    }
+    Ok(())
+}
+
+fn print_records(reader: &PriceReader, from: usize, to: usize) -> Result<(), Error> {
+    let scale = reader.header().map_or(DEFAULT_PRICE_SCALE, |header| header.price_scale);
+    let price = |value: u64| format_fixed(value, scale);
+
+    match reader.layout() {
+        RecordLayout::Close => {
+            for (index, entry) in (from..).zip(reader.records::<SolanaPriceEntry>(from, to)?) {
+                let entry = entry?;
+                println!("{:>10}  {}  {}", index, format_time(entry.time), price(entry.close_price));
+            }
+        }
+        RecordLayout::Ohlcv => {
+            for (index, entry) in (from..).zip(reader.records::<OhlcvEntry>(from, to)?) {
+                let entry = entry?;
+                println!(
+                    "{:>10}  {}  o {}  h {}  l {}  c {}  vol {}  trades {}",
+                    index,
+                    format_time(entry.time),
+                    price(entry.open),
+                    price(entry.high),
+                    price(entry.low),
+                    price(entry.close),
+                    format_fixed(entry.volume, VOLUME_SCALE),
+                    entry.trades,
+                );
+            }
+        }
+    }
+    Ok(())
+}
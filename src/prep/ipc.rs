@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufWriter,
+    sync::Arc,
+};
+
+use arrow_array::{ArrayRef, Decimal128Array, RecordBatch, TimestampSecondArray};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+
+use crate::prep::error::Error;
+use crate::prep::export::PRICE_PRECISION;
+use crate::prep::record::SolanaPriceEntry;
+
+/** What an Arrow IPC output records about its data, on both columns and the schema. `SqliteSink` keeps the same in `meta`. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArrowMetadata {
+    pub symbol: String,
+    pub source: String, // Where the records came from, `parse_binance` puts the source folders here.
+    pub interval_secs: u32,
+}
+
+impl ArrowMetadata {
+    /** Key and value pairs, as they're written. */
+    pub fn pairs(&self) -> [(&'static str, String); 3] {
+        [
+            ("symbol", self.symbol.clone()),
+            ("source", self.source.clone()),
+            ("interval_secs", self.interval_secs.to_string()),
+        ]
+    }
+
+    fn to_map(&self) -> HashMap<String, String> {
+        self.pairs().into_iter().map(|(key, value)| (key.to_string(), value)).collect()
+    }
+}
+
+/**
+ * Writes close records as an Arrow IPC file, `time` as `timestamp[s, UTC]` and `close` as `decimal128(20, scale)`.
+ * Every `write` is one record batch, buffers are 64-byte aligned so readers can memory-map the file.
+ */
+pub struct ArrowIpcWriter {
+    writer: FileWriter<BufWriter<File>>,
+    schema: SchemaRef,
+    scale: u8,
+    rows: u64,
+}
+
+impl ArrowIpcWriter {
+    pub fn create(path: &str, metadata: &ArrowMetadata, scale: u8) -> Result<ArrowIpcWriter, Error> {
+        let map = metadata.to_map();
+        let schema = Arc::new(Schema::new_with_metadata(
+            vec![
+                Field::new("time", DataType::Timestamp(TimeUnit::Second, Some("UTC".into())), false)
+                    .with_metadata(map.clone()),
+                Field::new("close", DataType::Decimal128(PRICE_PRECISION, scale as i8), false)
+                    .with_metadata(map.clone()),
+            ],
+            map,
+        ));
+
+        let writer = FileWriter::try_new_buffered(File::create(path)?, &schema)?;
+        Ok(ArrowIpcWriter { writer, schema, scale, rows: 0 })
+    }
+
+    pub fn write(&mut self, entries: &[SolanaPriceEntry]) -> Result<(), Error> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let time_column: ArrayRef = Arc::new(
+            TimestampSecondArray::from_iter_values(entries.iter().map(|entry| entry.time as i64)).with_timezone("UTC"),
+        );
+        let close_column: ArrayRef = Arc::new(
+            Decimal128Array::from_iter_values(entries.iter().map(|entry| entry.close_price as i128))
+                .with_precision_and_scale(PRICE_PRECISION, self.scale as i8)?,
+        );
+        self.writer.write(&RecordBatch::try_new(self.schema.clone(), vec![time_column, close_column])?)?;
+        self.rows += entries.len() as u64;
+        Ok(())
+    }
+
+    /** Writes the footer and flushes, returns the number of records written. Syncing is left to `commit_temp`. */
+    pub fn finish(mut self) -> Result<u64, Error> {
+        self.writer.finish()?;
+        Ok(self.rows)
+    }
+}
@@ -0,0 +1,566 @@
+use std::{
+    cell::Cell,
+    ffi::OsString,
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+    num::ParseIntError,
+};
+
+use zip::ZipArchive;
+
+use crate::prep::decimal::{parse_fixed, DecimalError, Rounding};
+use crate::prep::error::Error;
+use crate::prep::header::DEFAULT_PRICE_SCALE;
+use crate::prep::record::{OhlcvEntry, SolanaPriceEntry, VOLUME_SCALE};
+
+// Column names of the documented 12 column kline layout, see the readme.
+pub const KLINE_COLUMNS: [&str; 12] = [
+    "open_time", "open", "high", "low", "close", "volume", "close_time",
+    "quote_volume", "count", "taker_buy_volume", "taker_buy_quote_volume", "ignore",
+];
+
+/** How kline values are turned into fixed point numbers. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub price_scale: u8,
+    pub rounding: Rounding,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            price_scale: DEFAULT_PRICE_SCALE,
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+/** Unit of the epoch timestamps in a kline file, Binance moved spot dumps from ms to µs in 2025. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Seconds,
+    Millis,
+    Micros,
+    Nanos,
+}
+
+impl TimeUnit {
+    /** Tells the unit apart by digit count, which holds for any date between 2001 and 2286. */
+    pub fn detect(time: &str) -> Option<TimeUnit> {
+        match time.len() {
+            10 => Some(TimeUnit::Seconds),
+            13 => Some(TimeUnit::Millis),
+            16 => Some(TimeUnit::Micros),
+            19 => Some(TimeUnit::Nanos),
+            _ => None,
+        }
+    }
+
+    pub fn per_second(self) -> u64 {
+        match self {
+            TimeUnit::Seconds => 1,
+            TimeUnit::Millis => 1_000,
+            TimeUnit::Micros => 1_000_000,
+            TimeUnit::Nanos => 1_000_000_000,
+        }
+    }
+}
+
+impl fmt::Display for TimeUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TimeUnit::Seconds => "seconds",
+            TimeUnit::Millis => "milliseconds",
+            TimeUnit::Micros => "microseconds",
+            TimeUnit::Nanos => "nanoseconds",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampError {
+    UnknownUnit(usize), // Digit count that isn't s, ms, µs or ns.
+    MixedUnits { expected: TimeUnit, found: TimeUnit }, // Unit differs from the file's first row.
+    OutOfRange, // Seconds don't fit a u32.
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampError::UnknownUnit(digits) => {
+                write!(f, "{} digit timestamp is not in seconds, milliseconds, microseconds or nanoseconds", digits)
+            }
+            TimestampError::MixedUnits { expected, found } => {
+                write!(f, "timestamp in {} but the file started in {}", found, expected)
+            }
+            TimestampError::OutOfRange => write!(f, "timestamp doesn't fit u32 seconds"),
+        }
+    }
+}
+
+/** How the columns of a kline CSV were found. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderMode {
+    Positional, // No header row, the documented 12 column order.
+    Named,      // Header row, columns mapped by name.
+}
+
+impl fmt::Display for HeaderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderMode::Positional => write!(f, "no header, positional columns"),
+            HeaderMode::Named => write!(f, "header row, columns by name"),
+        }
+    }
+}
+
+// Physical column of each entry in `KLINE_COLUMNS`, `None` when a header row leaves it out.
+#[derive(Debug, Clone, Copy)]
+struct ColumnMap([Option<usize>; 12]);
+
+impl ColumnMap {
+    // Rows are as wide as the first one, the csv reader refuses any that aren't.
+    fn positional(width: usize) -> ColumnMap {
+        ColumnMap(std::array::from_fn(|column| Some(column).filter(|&column| column < width)))
+    }
+
+    fn from_header(header: &csv::StringRecord) -> ColumnMap {
+        let mut map = ColumnMap([None; 12]);
+        for (index, name) in header.iter().enumerate() {
+            if let Some(column) = column_for_name(name) {
+                map.0[column].get_or_insert(index);
+            }
+        }
+        map
+    }
+
+    fn missing(&self, required: &[usize]) -> Option<usize> {
+        required.iter().copied().find(|&column| self.0[column].is_none())
+    }
+}
+
+// Columns each output reads, as positions in `KLINE_COLUMNS`.
+const CLOSE_COLUMNS: [usize; 2] = [0, 4];
+// Close time and "ignore" are not needed.
+const OHLCV_COLUMNS: [usize; 10] = [0, 1, 2, 3, 4, 5, 7, 8, 9, 10];
+
+// Binance and common exports spell some columns differently.
+fn column_for_name(name: &str) -> Option<usize> {
+    let name = name.trim().trim_start_matches('\u{feff}').to_lowercase().replace([' ', '-'], "_");
+    let canonical = match name.as_str() {
+        "open_time" | "opentime" | "timestamp" => "open_time",
+        "number_of_trades" | "trades" | "trade_count" => "count",
+        "quote_asset_volume" | "quote_volume" => "quote_volume",
+        "taker_buy_base_asset_volume" | "taker_buy_base_volume" => "taker_buy_volume",
+        "taker_buy_quote_asset_volume" => "taker_buy_quote_volume",
+        other => other,
+    };
+    KLINE_COLUMNS.iter().position(|column| *column == canonical)
+}
+
+// A header row is anything whose first field isn't a number, data rows start with a timestamp.
+fn is_header_row(record: &csv::StringRecord) -> bool {
+    record.get(0).is_some_and(|first| {
+        let first = first.trim();
+        first.is_empty() || !first.bytes().all(|b| b.is_ascii_digit())
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldErrorKind {
+    Decimal(DecimalError),
+    Integer(ParseIntError),
+    Timestamp(TimestampError),
+}
+
+impl fmt::Display for FieldErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldErrorKind::Decimal(err) => write!(f, "{}", err),
+            FieldErrorKind::Integer(err) => write!(f, "{}", err),
+            FieldErrorKind::Timestamp(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/** A CSV value that couldn't be parsed, with where it was found. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub row: u64,      // Line in the CSV, 1 based.
+    pub column: usize, // 0 based, see `KLINE_COLUMNS`.
+    pub value: String,
+    pub kind: FieldErrorKind,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = KLINE_COLUMNS.get(self.column).unwrap_or(&"?");
+        write!(f, "row {}, column {} ({}), value {:?}: {}", self.row, self.column, name, self.value, self.kind)
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+pub(crate) fn is_zip(path: &str) -> bool {
+    path.to_lowercase().ends_with(".zip")
+}
+
+pub(crate) fn is_csv(path: &str) -> bool {
+    path.to_lowercase().ends_with(".csv")
+}
+
+/** One CSV row with enough context to say where a bad value came from. */
+struct Row<'a> {
+    record: &'a csv::StringRecord,
+    columns: &'a ColumnMap,
+    line: u64,
+    unit: &'a Cell<Option<TimeUnit>>, // Set by the first timestamp in the file.
+}
+
+impl Row<'_> {
+    fn field(&self, column: usize) -> &str {
+        self.columns.0[column]
+            .and_then(|index| self.record.get(index))
+            .unwrap_or("")
+    }
+
+    fn error(&self, column: usize, kind: FieldErrorKind) -> FieldError {
+        FieldError {
+            row: self.line,
+            column,
+            value: self.field(column).to_string(),
+            kind,
+        }
+    }
+
+    fn fixed(&self, column: usize, scale: u8, rounding: Rounding) -> Result<u64, FieldError> {
+        parse_fixed(self.field(column), scale, rounding)
+            .map_err(|err| self.error(column, FieldErrorKind::Decimal(err)))
+    }
+
+    fn price(&self, column: usize, options: &ParseOptions) -> Result<u64, FieldError> {
+        self.fixed(column, options.price_scale, options.rounding)
+    }
+
+    fn integer<T: std::str::FromStr<Err = ParseIntError>>(&self, column: usize) -> Result<T, FieldError> {
+        self.field(column)
+            .parse::<T>()
+            .map_err(|err| self.error(column, FieldErrorKind::Integer(err)))
+    }
+
+    /** Epoch seconds out of a s/ms/µs/ns timestamp, every row of a file has to use the same unit. */
+    fn time(&self, column: usize) -> Result<u32, FieldError> {
+        let time = self.field(column);
+        let timestamp_error = |err| self.error(column, FieldErrorKind::Timestamp(err));
+
+        let value = time
+            .parse::<u64>()
+            .map_err(|err| self.error(column, FieldErrorKind::Integer(err)))?;
+        let unit = TimeUnit::detect(time).ok_or_else(|| timestamp_error(TimestampError::UnknownUnit(time.len())))?;
+
+        match self.unit.get() {
+            None => self.unit.set(Some(unit)),
+            Some(expected) if expected != unit => {
+                return Err(timestamp_error(TimestampError::MixedUnits { expected, found: unit }));
+            }
+            Some(_) => {}
+        }
+
+        u32::try_from(value / unit.per_second()).map_err(|_| timestamp_error(TimestampError::OutOfRange))
+    }
+}
+
+/** Reads a kline CSV, or every CSV inside a `.zip` archive, straight from disk. */
+pub fn get_vector(path: &str, options: &ParseOptions) -> Result<Vec<SolanaPriceEntry>, Error> {
+    read_klines(path, options, &CLOSE_COLUMNS, close_row)
+}
+
+/** Like `get_vector` but keeps every kline column. */
+pub fn get_ohlcv_vector(path: &str, options: &ParseOptions) -> Result<Vec<OhlcvEntry>, Error> {
+    read_klines(path, options, &OHLCV_COLUMNS, ohlcv_row)
+}
+
+type RowParser<T> = fn(&Row, &ParseOptions) -> Result<T, FieldError>;
+
+// `required` are the columns `row` reads, a file without one of them is refused.
+fn read_klines<T>(path: &str, options: &ParseOptions, required: &[usize], row: RowParser<T>) -> Result<Vec<T>, Error> {
+    let file_path = OsString::from(path);
+    let file = File::open(file_path)?;
+
+    if !is_zip(path) {
+        let (vec, report) = read_csv(file, path, options, required, row)?;
+        report.print(path);
+        return Ok(vec);
+    }
+
+    let mut archive = ZipArchive::new(BufReader::new(file))?;
+    let mut vec: Vec<T> = Vec::new();
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        if !entry.is_file() || !is_csv(entry.name()) {
+            continue;
+        }
+        // Decompressed as the CSV reader pulls, nothing is unpacked to disk.
+        let name = format!("{}/{}", path, entry.name());
+        let (entries, report) = read_csv(entry, &name, options, required, row)?;
+        report.print(&name);
+        vec.extend(entries);
+    }
+
+    Ok(vec)
+}
+
+/** How a CSV was read, printed once per file. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvReport {
+    pub header: HeaderMode,
+    pub unit: Option<TimeUnit>, // `None` for a file without rows.
+}
+
+impl CsvReport {
+    fn print(&self, name: &str) {
+        let name = name.rsplit('/').next().unwrap_or(name);
+        match self.unit {
+            Some(unit) => println!("Read {:?}: {}, timestamps in {}", name, self.header, unit),
+            None => println!("Read {:?}: {}, no rows", name, self.header),
+        }
+    }
+}
+
+// `name` is only used to say where a bad value was.
+fn read_csv<R: Read, T>(
+    reader: R,
+    name: &str,
+    options: &ParseOptions,
+    required: &[usize],
+    row: RowParser<T>,
+) -> Result<(Vec<T>, CsvReport), Error> {
+    let mut vec: Vec<T> = Vec::new();
+
+    // Older dumps have no header row, so the first row is only skipped once we know it's one.
+    let mut rdr = csv::ReaderBuilder::new().has_headers(false).from_reader(reader);
+    let mut records = rdr.records().peekable();
+
+    let (header, columns) = match records.peek() {
+        Some(Ok(first)) if is_header_row(first) => {
+            let columns = ColumnMap::from_header(first);
+            records.next();
+            (HeaderMode::Named, columns)
+        }
+        Some(Ok(first)) => (HeaderMode::Positional, ColumnMap::positional(first.len())),
+        _ => (HeaderMode::Positional, ColumnMap::positional(KLINE_COLUMNS.len())),
+    };
+    // Every column the output reads has to be there, rows aren't dropped one by one.
+    if let Some(column) = columns.missing(required) {
+        let reason = match header {
+            HeaderMode::Named => format!("its header row has no {} column", KLINE_COLUMNS[column]),
+            HeaderMode::Positional => format!("it has no header row and ends before {} (column {})", KLINE_COLUMNS[column], column),
+        };
+        return Err(Error::Format(format!("Can't read {}: {}", name, reason)));
+    }
+
+    let unit = Cell::new(None);
+    for result in records {
+        let record = result?;
+        let line = record.position().map_or(0, |position| position.line());
+        let parsed = row(&Row { record: &record, columns: &columns, line, unit: &unit }, options);
+        vec.push(parsed.map_err(|err| Error::parse(name, err))?);
+    }
+
+    Ok((vec, CsvReport { header, unit: unit.get() }))
+}
+
+fn close_row(row: &Row, options: &ParseOptions) -> Result<SolanaPriceEntry, FieldError> {
+    Ok(SolanaPriceEntry {
+        time: row.time(0)?,
+        close_price: row.price(4, options)?,
+    })
+}
+
+// Columns as documented in the readme.
+fn ohlcv_row(row: &Row, options: &ParseOptions) -> Result<OhlcvEntry, FieldError> {
+    let volume = |column: usize| row.fixed(column, VOLUME_SCALE, options.rounding);
+
+    Ok(OhlcvEntry {
+        time: row.time(0)?,
+        open: row.price(1, options)?,
+        high: row.price(2, options)?,
+        low: row.price(3, options)?,
+        close: row.price(4, options)?,
+        volume: volume(5)?,
+        quote_volume: volume(7)?,
+        trades: row.integer(8)?,
+        taker_buy_base_volume: volume(9)?,
+        taker_buy_quote_volume: volume(10)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prep::atomic::test_path;
+    use std::io::Write;
+
+    const ROW: &str = "1700000000000,2.1,2.5,2.0,2.25,10.5,1700000000999,23.1,7,4.5,9.9,0";
+
+    fn write_csv(name: &str, contents: &str) -> String {
+        let path = test_path(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn candle(time: u32) -> OhlcvEntry {
+        OhlcvEntry {
+            time,
+            open: 2_100,
+            high: 2_500,
+            low: 2_000,
+            close: 2_250,
+            volume: 1_050_000_000,
+            quote_volume: 2_310_000_000,
+            trades: 7,
+            taker_buy_base_volume: 450_000_000,
+            taker_buy_quote_volume: 990_000_000,
+        }
+    }
+
+    fn pairs(closes: &[SolanaPriceEntry]) -> Vec<(u32, u64)> {
+        closes.iter().map(|entry| (entry.time, entry.close_price)).collect()
+    }
+
+    #[test]
+    fn headerless_rows_use_the_documented_order() {
+        let path = write_csv("kline-positional", &format!("{}\n{}\n", ROW, ROW.replacen("1700000000000", "1700000001000", 1)));
+        let options = ParseOptions::default();
+        assert_eq!(get_ohlcv_vector(&path, &options).unwrap(), vec![candle(1_700_000_000), candle(1_700_000_001)]);
+        let closes = get_vector(&path, &options).unwrap();
+        assert_eq!(pairs(&closes), [(1_700_000_000, 2_250), (1_700_000_001, 2_250)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reordered_aliased_and_bom_headers_map_by_name() {
+        let header = "\u{feff}Timestamp,Close,Open,High,Low,Volume,Quote Asset Volume,Number of Trades,\
+                      taker-buy-base-asset-volume,Taker_Buy_Quote_Asset_Volume";
+        let path = write_csv("kline-named", &format!("{}\n1700000000000,2.25,2.1,2.5,2.0,10.5,23.1,7,4.5,9.9\n", header));
+        assert_eq!(get_ohlcv_vector(&path, &ParseOptions::default()).unwrap(), vec![candle(1_700_000_000)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_columns_are_refused_by_name() {
+        // Enough for closes, not for candles.
+        let path = write_csv("kline-missing", "open_time,open,high,low,close,volume\n1700000000000,2.1,2.5,2.0,2.25,10.5\n");
+        let options = ParseOptions::default();
+        assert_eq!(get_vector(&path, &options).unwrap().len(), 1);
+        match get_ohlcv_vector(&path, &options) {
+            Err(Error::Format(message)) => assert!(message.contains("quote_volume"), "{}", message),
+            other => panic!("expected a format error, got {:?}", other),
+        }
+
+        std::fs::write(&path, "open_time,open\n1700000000000,2.1\n").unwrap();
+        match get_vector(&path, &options) {
+            Err(Error::Format(message)) => assert!(message.contains("close"), "{}", message),
+            other => panic!("expected a format error, got {:?}", other),
+        }
+
+        // Without a header too few columns can't be mapped either.
+        std::fs::write(&path, "1700000000000,2.1,2.5,2.0,2.25,10.5\n").unwrap();
+        assert_eq!(get_vector(&path, &options).unwrap().len(), 1);
+        assert!(matches!(get_ohlcv_vector(&path, &options), Err(Error::Format(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn csvs_inside_a_zip_are_read_in_order() {
+        let path = test_path("kline-archive.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("SOLUSDT-1s-2024-01-01.csv", options).unwrap();
+        writeln!(zip, "{}", ROW).unwrap();
+        zip.start_file("readme.txt", options).unwrap();
+        writeln!(zip, "not a kline").unwrap();
+        zip.start_file("SOLUSDT-1s-2024-01-02.csv", options).unwrap();
+        writeln!(zip, "open_time,close\n1700000001000,2.3").unwrap();
+        zip.finish().unwrap();
+
+        let closes = get_vector(&path, &ParseOptions::default()).unwrap();
+        assert_eq!(pairs(&closes), [(1_700_000_000, 2_250), (1_700_000_001, 2_300)]);
+        // The second CSV lacks the candle columns.
+        assert!(matches!(get_ohlcv_vector(&path, &ParseOptions::default()), Err(Error::Format(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn time_of(value: &str, unit: &Cell<Option<TimeUnit>>) -> Result<u32, FieldErrorKind> {
+        let record = csv::StringRecord::from(vec![value]);
+        let columns = ColumnMap::positional(1);
+        Row { record: &record, columns: &columns, line: 1, unit }.time(0).map_err(|err| err.kind)
+    }
+
+    #[test]
+    fn units_are_told_apart_by_digit_count() {
+        let units = [
+            ("1700000000", TimeUnit::Seconds),
+            ("1700000000000", TimeUnit::Millis),
+            ("1700000000000000", TimeUnit::Micros),
+            ("1700000000000000000", TimeUnit::Nanos),
+        ];
+        for (time, unit) in units {
+            assert_eq!(TimeUnit::detect(time), Some(unit));
+            assert_eq!(time_of(time, &Cell::new(None)), Ok(1_700_000_000));
+        }
+
+        for time in ["170000000", "17000000000", "170000000000000", "17000000000000000000"] {
+            assert_eq!(TimeUnit::detect(time), None);
+            let unknown = FieldErrorKind::Timestamp(TimestampError::UnknownUnit(time.len()));
+            assert_eq!(time_of(time, &Cell::new(None)).unwrap_err(), unknown);
+        }
+    }
+
+    #[test]
+    fn a_file_sticks_to_its_first_unit() {
+        let unit = Cell::new(None);
+        assert_eq!(time_of("1700000000000", &unit), Ok(1_700_000_000));
+        assert_eq!(unit.get(), Some(TimeUnit::Millis));
+
+        let mixed = TimestampError::MixedUnits { expected: TimeUnit::Millis, found: TimeUnit::Micros };
+        assert_eq!(time_of("1700000001000000", &unit), Err(FieldErrorKind::Timestamp(mixed)));
+        assert_eq!(time_of("1700000002000", &unit), Ok(1_700_000_002));
+    }
+
+    #[test]
+    fn seconds_past_u32_are_out_of_range() {
+        let out_of_range = Err(FieldErrorKind::Timestamp(TimestampError::OutOfRange));
+        assert_eq!(time_of("4294967295", &Cell::new(None)), Ok(u32::MAX));
+        assert_eq!(time_of("4294967296", &Cell::new(None)), out_of_range);
+        assert_eq!(time_of("9999999999999", &Cell::new(None)), out_of_range);
+        assert_eq!(time_of("9999999999999999999", &Cell::new(None)), out_of_range);
+    }
+
+    #[test]
+    fn files_switching_from_ms_to_us_read_each_in_their_own_unit() {
+        let path = test_path("kline-switch.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("SOLUSDT-1s-2024-12-31.csv", options).unwrap();
+        writeln!(zip, "1735689598000,0,0,0,2.1,0,0,0,0,0,0,0\n1735689599000,0,0,0,2.2,0,0,0,0,0,0,0").unwrap();
+        zip.start_file("SOLUSDT-1s-2025-01-01.csv", options).unwrap();
+        writeln!(zip, "1735689600000000,0,0,0,2.3,0,0,0,0,0,0,0").unwrap();
+        zip.finish().unwrap();
+
+        let closes = get_vector(&path, &ParseOptions::default()).unwrap();
+        assert_eq!(pairs(&closes), [(1_735_689_598, 2_100), (1_735_689_599, 2_200), (1_735_689_600, 2_300)]);
+        std::fs::remove_file(&path).unwrap();
+
+        // Within one file the switch is refused, with the row it happened in.
+        let path = write_csv("kline-switch.csv", "1735689599000,0,0,0,2.2,0,0,0,0,0,0,0\n1735689600000000,0,0,0,2.3,0,0,0,0,0,0,0\n");
+        match get_vector(&path, &ParseOptions::default()) {
+            Err(Error::Parse { row: 2, col: 0, kind: FieldErrorKind::Timestamp(TimestampError::MixedUnits { .. }), .. }) => {}
+            other => panic!("expected mixed units on row 2, got {:?}", other),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use std::{path::Path, str::FromStr};
+
+use crate::prep::atomic::{commit_temp, temp_path};
+use crate::prep::error::Error;
+
+/** One ingested source file, as recorded next to the merged output. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub file: String,        // File name without the folder.
+    pub size: u64,           // Size in bytes when it was ingested.
+    pub sha256: String,
+    pub first_time: u32,     // Timestamp range found in the source file.
+    pub last_time: u32,
+    pub records_written: u64, // Entries that made it into the output after dropping duplicates.
+    pub output_records: u64, // Output length once this file was committed.
+}
+
+const COLUMNS: [&str; 7] = [
+    "file", "size", "sha256", "first_time", "last_time", "records_written", "output_records",
+];
+
+pub fn manifest_path(dest_file: &str) -> String {
+    format!("{}.manifest", dest_file)
+}
+
+#[derive(Debug, Default)]
+pub struct Manifest {
+    path: String,
+    pub entries: Vec<ManifestEntry>,
+}
+
+fn number<T: FromStr>(path: &str, column: &str, value: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::Format(format!("Manifest {} has an invalid {} {:?}", path, column, value)))
+}
+
+impl Manifest {
+    /** Loads the manifest for `dest_file`, empty if there isn't one yet. */
+    pub fn load(dest_file: &str) -> Result<Manifest, Error> {
+        let path = manifest_path(dest_file);
+        let mut entries = Vec::new();
+
+        if Path::new(&path).is_file() {
+            let mut rdr = csv::Reader::from_path(&path)?;
+            for result in rdr.records() {
+                let record = result?;
+                let field = |index: usize| {
+                    record.get(index).ok_or_else(|| {
+                        Error::Format(format!("Manifest {} is missing column {}", path, COLUMNS[index]))
+                    })
+                };
+                entries.push(ManifestEntry {
+                    file: field(0)?.to_string(),
+                    size: number(&path, COLUMNS[1], field(1)?)?,
+                    sha256: field(2)?.to_string(),
+                    first_time: number(&path, COLUMNS[3], field(3)?)?,
+                    last_time: number(&path, COLUMNS[4], field(4)?)?,
+                    records_written: number(&path, COLUMNS[5], field(5)?)?,
+                    output_records: number(&path, COLUMNS[6], field(6)?)?,
+                });
+            }
+        }
+
+        Ok(Manifest { path, entries })
+    }
+
+    /** Output length covered by the manifest, `None` when nothing was recorded yet. */
+    pub fn committed_records(&self) -> Option<u64> {
+        self.entries.last().map(|entry| entry.output_records)
+    }
+
+    /** True when a file with this name and size was already ingested. */
+    pub fn contains(&self, file: &str, size: u64) -> bool {
+        self.entries.iter().any(|entry| entry.file == file && entry.size == size)
+    }
+
+    pub fn push(&mut self, entry: ManifestEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /** Writes the whole manifest through a temp file, call it after the output was committed. */
+    pub fn save(&self) -> Result<(), Error> {
+        let temp = temp_path(&self.path);
+        let mut wtr = csv::Writer::from_path(&temp)?;
+
+        wtr.write_record(COLUMNS)?;
+        for entry in self.entries.iter() {
+            wtr.write_record([
+                entry.file.clone(),
+                entry.size.to_string(),
+                entry.sha256.clone(),
+                entry.first_time.to_string(),
+                entry.last_time.to_string(),
+                entry.records_written.to_string(),
+                entry.output_records.to_string(),
+            ])?;
+        }
+        wtr.flush()?;
+        drop(wtr);
+
+        commit_temp(&temp, &self.path)
+    }
+}
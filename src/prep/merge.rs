@@ -1,21 +1,32 @@
 use std::{    
-    error::Error,
-    ffi::OsString,
     fs::File,    
-    io::{BufWriter, Write, BufReader, Read},
+    io::{BufWriter, Write, BufReader, Read, Seek, SeekFrom},
 };
 use std::fs;
 use std::path::Path;
 use std::io;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
-pub struct SolanaPriceEntry {
-    time: u32, // Timestamp in seconds since epoch.
-    close_price: u32, // Integer price with 3 decimal places included.
-}
+use crate::prep::atomic::{commit_temp, temp_path};
+use crate::prep::checksum::{quarantine, sha256_file, verify_checksum, ChecksumPolicy, ChecksumStatus, ChecksumSummary};
+use crate::prep::decimal::format_time;
+use crate::prep::error::Error;
+use crate::prep::header::{
+    read_header, read_header_unchecked, record_geometry, write_header, FileHeader, RecordLayout,
+    PriceFormat, PriceWidth, DEFAULT_INTERVAL_SECS, DEFAULT_SYMBOL, SYMBOL_SIZE,
+};
+use crate::prep::delta::{DeltaWriter, DEFAULT_BLOCK_RECORDS};
+use crate::prep::frames::{FrameWriter, DEFAULT_FRAME_RECORDS, DEFAULT_ZSTD_LEVEL};
+use crate::prep::ipc::{ArrowIpcWriter, ArrowMetadata};
+use crate::prep::kline::{get_ohlcv_vector, get_vector, is_csv, is_zip, ParseOptions};
+use crate::prep::manifest::{Manifest, ManifestEntry};
+use crate::prep::parallel::ordered_parallel;
+use crate::prep::reader::PriceReader;
+use crate::prep::record::{OhlcvEntry, Record, SolanaPriceEntry};
+use crate::prep::sqlite::SqliteSink;
 
 fn filename(full_path: &str) -> String {
-    if let Some(filename) = full_path.split('/').last() {
+    if let Some(filename) = full_path.split('/').next_back() {
         filename.to_string()
     } else {
         full_path.to_string()
@@ -28,9 +39,8 @@ fn fetch_files_alphabetically(dir_path: &str) -> io::Result<Vec<String>> {
     let path = Path::new(dir_path);    
     
     // Read the directory entries.
-    let entries = fs::read_dir(path).or_else(|err| {
+    let entries = fs::read_dir(path).inspect_err(|err| {
         eprintln!("Error reading directory {}: {}", dir_path, err);
-        Err(err)
     })?;        
     
     // Collect entries into a vector first so we can count and process them.
@@ -51,15 +61,156 @@ fn fetch_files_alphabetically(dir_path: &str) -> io::Result<Vec<String>> {
         })
         .collect();
     
+    // Only the kline archives and CSVs, skip .CHECKSUM sidecars and anything else.
+    files.retain(|file| is_zip(file) || is_csv(file));
+
     // Sort alphabetically by full path (case-insensitive).
-    files.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()));
-    
+    files.sort_by_key(|a| a.to_lowercase());
+
+    // Folders can hold both the archive and its unpacked CSV, read each only once.
+    files.dedup_by(|next, kept| {
+        let duplicate = file_stem(next).eq_ignore_ascii_case(file_stem(kept));
+        if duplicate {
+            println!("Skipping {:?}, already have {:?}", filename(next), filename(kept));
+        }
+        duplicate
+    });
+
     Ok(files)
 }
 
+// Path without the .zip/.csv extension.
+fn file_stem(path: &str) -> &str {
+    path.rfind('.').map_or(path, |dot| &path[..dot])
+}
+
+/** Record layout `parse_binance` writes. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    #[default]
+    Close, // Time and close price, see `SolanaPriceEntry`.
+    Ohlcv, // Every kline column, see `OhlcvEntry`.
+    Arrow,  // Time and close as an Arrow IPC file instead of the `.dat` format, see `ArrowIpcWriter`.
+    Sqlite, // Time and close upserted into a SQLite database, see `SqliteSink`.
+    Delta,  // Time and close in delta coded blocks, see `DeltaWriter`.
+    Zstd,   // Time and close in zstd frames, see `FrameWriter`.
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<OutputMode, String> {
+        match name {
+            "close" => Ok(OutputMode::Close),
+            "ohlcv" => Ok(OutputMode::Ohlcv),
+            "arrow" => Ok(OutputMode::Arrow),
+            "sqlite" => Ok(OutputMode::Sqlite),
+            "delta" => Ok(OutputMode::Delta),
+            "zstd" => Ok(OutputMode::Zstd),
+            _ => Err(format!("Unknown output mode {:?}, use close, ohlcv, arrow, sqlite, delta or zstd", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    pub output: OutputMode,
+    pub symbol: String,      // Recorded in the header of a new output, checked against an existing one.
+    pub interval_secs: u32,  // Likewise, the kline interval of the sources.
+    pub format: PriceFormat, // Decimals kept from the source prices, how they're rounded and how wide they're stored.
+    pub checksum_policy: ChecksumPolicy,
+    pub overlap_policy: OverlapPolicy, // New sources overlapping the existing output, see `write_records`.
+    pub repair: bool, // Truncate a torn trailing record in the existing output instead of refusing it.
+    pub jobs: usize,  // Files hashed and parsed at once, 0 for one per CPU core.
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            output: OutputMode::default(),
+            symbol: DEFAULT_SYMBOL.to_string(),
+            interval_secs: DEFAULT_INTERVAL_SECS,
+            format: PriceFormat::default(),
+            checksum_policy: ChecksumPolicy::default(),
+            overlap_policy: OverlapPolicy::SkipOverlap, // Monthly files arrive after their days were merged from daily ones.
+            repair: false,
+            jobs: 0,
+        }
+    }
+}
+
 /** Turns it one file. */
-pub fn parse_binance(dest_file: &str, parse_files: &[&str]) -> Result<(), Box<dyn Error>> {    
- 
+pub fn parse_binance(dest_file: &str, parse_files: &[&str], options: &MergeOptions) -> Result<(), Error> {
+    options.format.validate()?;
+    if options.symbol.is_empty() || !options.symbol.is_ascii() || options.symbol.len() > SYMBOL_SIZE {
+        return Err(Error::Format(format!("Symbol {:?} must be ASCII and 1 to {} bytes", options.symbol, SYMBOL_SIZE)));
+    }
+    if options.interval_secs == 0 {
+        return Err(Error::Format("Interval must be at least one second".to_string()));
+    }
+
+    match options.output {
+        OutputMode::Close => merge_sources(dest_file, parse_files, options, get_vector),
+        OutputMode::Ohlcv => merge_sources(dest_file, parse_files, options, get_ohlcv_vector),
+        OutputMode::Arrow => merge_rebuilt(dest_file, parse_files, options, |temp| {
+            ArrowIpcWriter::create(temp, &source_metadata(parse_files, options), options.format.scale)
+        }),
+        OutputMode::Sqlite => merge_sqlite(dest_file, parse_files, options),
+        OutputMode::Delta => merge_rebuilt(dest_file, parse_files, options, |temp| {
+            DeltaWriter::create(temp, &close_header(options), DEFAULT_BLOCK_RECORDS)
+        }),
+        OutputMode::Zstd => merge_rebuilt(dest_file, parse_files, options, |temp| {
+            FrameWriter::create(temp, &close_header(options), DEFAULT_FRAME_RECORDS, DEFAULT_ZSTD_LEVEL)
+        }),
+    }
+}
+
+type FileParser<R> = fn(&str, &ParseOptions) -> Result<Vec<R>, Error>;
+
+/** A source file as a worker hands it to the writer. */
+struct ParsedFile<R> {
+    sha256: String,
+    status: ChecksumStatus,
+    data: Vec<R>, // Left empty on a checksum mismatch, the file is refused or quarantined anyway.
+}
+
+// Memory a parsed file holds while it waits for the writer.
+fn parsed_bytes<R>(parsed: &Result<ParsedFile<R>, Error>) -> usize {
+    parsed.as_ref().map_or(0, |parsed| parsed.data.len() * std::mem::size_of::<R>())
+}
+
+fn parse_source<R>(file: &str, options: &ParseOptions, parse: FileParser<R>) -> Result<ParsedFile<R>, Error> {
+    let sha256 = sha256_file(file)?;
+    let status = verify_checksum(file, &sha256)?;
+    let data = match status {
+        ChecksumStatus::Mismatch { .. } => Vec::new(),
+        _ => parse(file, options)?,
+    };
+    Ok(ParsedFile { sha256, status, data })
+}
+
+fn merge_sources<R: Record + Send>(
+    dest_file: &str,
+    parse_files: &[&str],
+    options: &MergeOptions,
+    parse: FileParser<R>,
+) -> Result<(), Error> {
+    let parse_options = ParseOptions {
+        price_scale: options.format.scale,
+        rounding: options.format.rounding,
+    };
+    let mut checksums = ChecksumSummary::default();
+    let mut manifest = Manifest::load(dest_file)?;
+    let mut skipped = 0;
+
+    let temp_file = temp_path(dest_file);
+    if !Path::new(dest_file).exists() && !manifest.entries.is_empty() {
+        println!("Output {} is missing, starting a new manifest", dest_file);
+        manifest.clear();
+    }
+
+    // Only new files, the manifest has the ones already merged.
+    let mut pending = Vec::new();
     for dir in parse_files.iter() {
  
         // Start in the past, so using monthly data.
@@ -67,166 +218,854 @@ pub fn parse_binance(dest_file: &str, parse_files: &[&str]) -> Result<(), Box<dy
  
         println!("Files found: {}", files.len());
  
-        for file in files.iter() {                       
-            let data = get_vector(&file).or_else(|err| {
-                eprintln!("Error processing file {}: {}", file, err);
-                Err(err)
-            })?;            
- 
-            println!("Total entries in file {:?}: {}", filename(&file), data.len());
+        for file in files {
+            let size = fs::metadata(&file)?.len();
+            if manifest.contains(&filename(&file), size) {
+                skipped += 1;
+            } else {
+                pending.push((file, size));
+            }
+        }
+    }
+
+    if skipped > 0 {
+        println!("Skipped {} files already in the manifest", skipped);
+    }
+
+    // Nothing to append, leave the output alone unless it's to be repaired.
+    if pending.is_empty() {
+        if Path::new(dest_file).exists() {
+            if options.repair {
+                let repaired = start_batch(dest_file, &temp_file, &manifest, options)
+                    .and_then(|()| commit_temp(&temp_file, dest_file));
+                discard_failed_batch(&temp_file, repaired)?;
+            } else {
+                check_torn_tail(dest_file, false)?;
+            }
+        }
+        println!("No new files to merge into {}", dest_file);
+        return Ok(());
+    }
+
+    // Last timestamp merged in this run, sources overlapping each other are deduplicated against it.
+    // Overlap with what the output held before is left to `write_records` and the overlap policy.
+    let mut high_water = None;
+    let mut total_dropped = 0;
+
+    // Files go into a copy of the output, committed with their manifest rows every `COMMIT_FILES` files or
+    // `COMMIT_BYTES` appended. A crash loses at most one batch, each batch after the first copies the output again.
+    let mut batch_open = false;
+    let (mut batch_files, mut batch_bytes) = (0, 0);
+    let record_size = R::LAYOUT.record_size(options.format.width) as u64;
+
+    // Hashing and parsing fan out, checks and writes below still go file by file in order.
+    let work = |(file, _): &(String, u64)| parse_source(file, &parse_options, parse);
+
+    let merged = ordered_parallel(&pending, options.jobs, work, parsed_bytes, |(file, size), parsed| {
+        let ParsedFile { sha256, status, mut data } = parsed.map_err(|err| {
+            eprintln!("Error processing file {}: {}", file, err);
+            err as Error
+        })?;
+
+        if !accept_checksum(file, status, options.checksum_policy, &mut checksums)? {
+            return Ok(());
+        }
  
-            // Append to the file.
-            write_to_file(dest_file, &data)?;    
+        println!("Total entries in file {:?}: {}", filename(file), data.len());
+        let (first_time, last_time) = match (data.first(), data.last()) {
+            (Some(first), Some(last)) => (first.time(), last.time()),
+            _ => (0, 0),
+        };
+
+        // Daily files repeat days the monthly files already cover.
+        let dropped = drop_overlap(&mut data, &mut high_water);
+        if dropped > 0 {
+            println!("Dropped {} duplicate entries from {:?}", dropped, filename(file));
+            total_dropped += dropped;
+        }
+
+        if !batch_open {
+            start_batch(dest_file, &temp_file, &manifest, options)?;
+            batch_open = true;
         }
-    }     
  
+        // Append to the file, a new one starts as a bare header.
+        if !Path::new(&temp_file).exists() {
+            let header = FileHeader {
+                layout: R::LAYOUT,
+                symbol: options.symbol.clone(),
+                interval_secs: options.interval_secs,
+                price_scale: options.format.scale,
+                price_width: options.format.width,
+                rounding: options.format.rounding,
+                ..FileHeader::default()
+            };
+            write_header(&mut File::create(&temp_file)?, &header)?;
+        }
+        let written = append_records(&temp_file, dest_file, &data, options.format, options.overlap_policy)?;
+        if written < data.len() as u64 {
+            println!("Skipped {} entries already in the output from {:?}", data.len() as u64 - written, filename(file));
+            total_dropped += data.len() - written as usize;
+        }
+
+        // Files whose whole span was just replaced have no records left in the output, merge them again next run.
+        if let (OverlapPolicy::OverwriteOverlap, Some(first), Some(last)) = (options.overlap_policy, data.first(), data.last()) {
+            let before = manifest.entries.len();
+            manifest.entries.retain(|entry| entry.first_time < first.time() || entry.last_time > last.time());
+            if manifest.entries.len() < before {
+                println!("Overwrote every entry of {} earlier files, dropped them from the manifest", before - manifest.entries.len());
+            }
+        }
+
+        manifest.push(ManifestEntry {
+            file: filename(file),
+            size: *size,
+            sha256,
+            first_time,
+            last_time,
+            records_written: written,
+            output_records: stored_records(&temp_file)?,
+        });
+
+        batch_files += 1;
+        batch_bytes += written * record_size;
+        if batch_files >= COMMIT_FILES || batch_bytes >= COMMIT_BYTES {
+            commit_batch(dest_file, &temp_file, &manifest, batch_files)?;
+            batch_open = false;
+            (batch_files, batch_bytes) = (0, 0);
+        }
+        Ok(())
+    });
+    let merged = match merged {
+        Ok(()) if batch_open => commit_batch(dest_file, &temp_file, &manifest, batch_files),
+        merged => merged,
+    };
+    discard_failed_batch(&temp_file, merged)?;
+
+    checksums.print();
+    println!("Duplicate entries dropped: {}", total_dropped);
+
     Ok(())
 }
 
-fn standardize_price(price: &str) -> Result<u32, Box<dyn Error>> {
-    let dot_pos = price.find('.').unwrap_or(price.len());
-    let before_dot = &price[..dot_pos];
-    let after_dot = if dot_pos < price.len() {
-        let remaining = &price[dot_pos + 1..];
-        if remaining.len() >= 3 {
-            &remaining[..3]
-        } else {
-            remaining
+// Source files merged between commits of the output and its manifest.
+const COMMIT_FILES: usize = 64;
+
+// Bytes appended after which a batch is committed early, about a dozen monthly 1s files.
+const COMMIT_BYTES: u64 = 256 << 20;
+
+/**
+ * Copies the output to `temp_file` for the next batch of appends, refusing a torn tail or repairing it in the copy,
+ * and drops anything past what the manifest has committed.
+ */
+fn start_batch(dest_file: &str, temp_file: &str, manifest: &Manifest, options: &MergeOptions) -> Result<(), Error> {
+    if !Path::new(dest_file).exists() {
+        if Path::new(temp_file).exists() {
+            fs::remove_file(temp_file)?;
         }
-    } else {
-        ""
-    };
-    let combined = format!("{}{}", before_dot, after_dot);
-    Ok(combined.parse::<u32>()?)
+        return Ok(());
+    }
+
+    if !options.repair {
+        check_torn_tail(dest_file, false)?;
+    }
+    fs::copy(dest_file, temp_file)?;
+    check_torn_tail(temp_file, options.repair)?;
+
+    // Anything past the last committed file was written without its manifest row.
+    if let Some(committed) = manifest.committed_records() {
+        let stored = stored_records(temp_file)?;
+        if stored > committed {
+            println!("Dropping {} uncommitted entries", stored - committed);
+            truncate_records(temp_file, committed)?;
+        } else if stored < committed {
+            return Err(Error::Format(format!(
+                "Output holds {} entries but its manifest records {}, remove the manifest to rebuild",
+                stored, committed
+            )));
+        }
+    }
+
+    let reader = PriceReader::open(temp_file)?;
+    if let Some(header) = reader.header() {
+        if header.symbol != options.symbol || header.interval_secs != options.interval_secs {
+            return Err(Error::Format(format!(
+                "{} holds {} at {}s, can't merge {} at {}s into it",
+                dest_file, header.symbol, header.interval_secs, options.symbol, options.interval_secs
+            )));
+        }
+    }
+    Ok(())
 }
 
-pub fn get_vector(path: &str) -> Result<Vec<SolanaPriceEntry>, Box<dyn Error>> {
-    let mut vec: Vec<SolanaPriceEntry> = Vec::new();
-    let file_path = OsString::from(path);
-    let file = File::open(file_path)?;
-    let mut rdr = csv::Reader::from_reader(file);        
- 
-    for result in rdr.records() {
-        let record = result?;
-        if let Some(time) = record.get(0) {           
-            if let Some(close_price) = record.get(4) {
- 
-                // Auto-detect format based on timestamp length.
-                let drop = if time.len() == 13 { 3 } else { 6 };
- 
-                // Extract the seconds component.
-                vec.push(SolanaPriceEntry{
-                    time: time[..time.len() - drop].parse::<u32>().map_err(|e| {
-                        eprintln!("TIMESTAMP PARSE FAILED: '{}'", &time[..time.len() - drop]);
-                        e
-                    })?,
-                    close_price: standardize_price(close_price)?
-                });          
+/** Removes the copy a failed batch was written to, the output keeps what was last committed. */
+fn discard_failed_batch(temp_file: &str, result: Result<(), Error>) -> Result<(), Error> {
+    if result.is_err() && Path::new(temp_file).exists() {
+        // The merge error is the one worth reporting, a copy that can't be removed is replaced next run.
+        let _ = fs::remove_file(temp_file);
+    }
+    result
+}
+
+/** Replaces the output with the batch, then saves the manifest. A manifest behind the output only costs a re-merge. */
+fn commit_batch(dest_file: &str, temp_file: &str, manifest: &Manifest, files: usize) -> Result<(), Error> {
+    if Path::new(temp_file).exists() {
+        commit_temp(temp_file, dest_file)?;
+        manifest.save()?;
+        println!("Committed {} files to {}", files, dest_file);
+    }
+    Ok(())
+}
+
+/**
+ * Records a file's checksum status and applies the policy to a mismatch, don't merge truncated or corrupted downloads.
+ * `Ok(false)` means the file was quarantined and is skipped.
+ */
+fn accept_checksum(
+    file: &str,
+    status: ChecksumStatus,
+    policy: ChecksumPolicy,
+    checksums: &mut ChecksumSummary,
+) -> Result<bool, Error> {
+    checksums.record(file, status.clone());
+
+    if let ChecksumStatus::Mismatch { expected, actual } = status {
+        match policy {
+            ChecksumPolicy::Refuse => {
+                checksums.print();
+                return Err(Error::Checksum { file: file.to_string(), expected, actual });
+            }
+            ChecksumPolicy::Quarantine => {
+                let moved = quarantine(file)?;
+                eprintln!("Checksum mismatch, moved {} to {}", filename(file), moved);
+                return Ok(false);
             }
         }
-    }      
- 
-    Ok(vec)
- }
+    }
+    Ok(true)
+}
+
+/** Output that takes close records and is rebuilt as a whole on each run, see `merge_rebuilt`. */
+trait CloseSink {
+    fn write_close(&mut self, entries: &[SolanaPriceEntry]) -> Result<(), Error>;
+    /** Completes the output, returns the number of records written. */
+    fn finish_close(self) -> Result<u64, Error>;
+}
+
+impl CloseSink for ArrowIpcWriter {
+    fn write_close(&mut self, entries: &[SolanaPriceEntry]) -> Result<(), Error> {
+        self.write(entries)
+    }
+
+    fn finish_close(self) -> Result<u64, Error> {
+        self.finish()
+    }
+}
+
+impl CloseSink for DeltaWriter {
+    fn write_close(&mut self, entries: &[SolanaPriceEntry]) -> Result<(), Error> {
+        self.write(entries)
+    }
+
+    fn finish_close(self) -> Result<u64, Error> {
+        self.finish()
+    }
+}
+
+impl CloseSink for FrameWriter {
+    fn write_close(&mut self, entries: &[SolanaPriceEntry]) -> Result<(), Error> {
+        self.write(entries)
+    }
+
+    fn finish_close(self) -> Result<u64, Error> {
+        self.finish()
+    }
+}
+
+/**
+ * Merges every source file into the output `create` opens on a temp file next to `dest_file`: Arrow IPC, delta or zstd.
+ * There's no manifest, the whole file is rebuilt on each run and replaced once it's through.
+ * `encode_delta` and `compress_frames` convert an existing `.dat` instead.
+ */
+fn merge_rebuilt<S, C>(dest_file: &str, parse_files: &[&str], options: &MergeOptions, create: C) -> Result<(), Error>
+where
+    S: CloseSink,
+    C: FnOnce(&str) -> Result<S, Error>,
+{
+    let temp_file = temp_path(dest_file);
+    let mut sink = create(&temp_file)?;
+    merge_close_records(parse_files, options, |data| sink.write_close(data))?;
+
+    let records = sink.finish_close()?;
+    commit_temp(&temp_file, dest_file)?;
+    println!("Wrote {} records to {}", records, dest_file);
+    Ok(())
+}
+
+/**
+ * Upserts every source file into the SQLite database at `dest_file`, then refreshes the rollups of the span written.
+ * Re-runs overwrite rows with the same timestamp, nothing is skipped.
+ */
+fn merge_sqlite(dest_file: &str, parse_files: &[&str], options: &MergeOptions) -> Result<(), Error> {
+    let mut sink = SqliteSink::open(dest_file, &source_metadata(parse_files, options), options.format.scale)?;
+    merge_close_records(parse_files, options, |data| sink.write(data))?;
+
+    let records = sink.finish()?;
+    println!("Wrote {} records to {}", records, dest_file);
+    Ok(())
+}
+
+fn close_header(options: &MergeOptions) -> FileHeader {
+    FileHeader {
+        symbol: options.symbol.clone(),
+        interval_secs: options.interval_secs,
+        price_scale: options.format.scale,
+        price_width: options.format.width,
+        rounding: options.format.rounding,
+        ..FileHeader::default()
+    }
+}
+
+fn source_metadata(parse_files: &[&str], options: &MergeOptions) -> ArrowMetadata {
+    ArrowMetadata {
+        symbol: options.symbol.clone(),
+        source: parse_files.join(","),
+        interval_secs: options.interval_secs,
+    }
+}
+
+/**
+ * Parses every file in `parse_files` and hands the close records of each to `write`, in order and without duplicates.
+ * For outputs that are rebuilt or upserted as a whole, so there's no manifest to skip files with.
+ */
+fn merge_close_records<W>(parse_files: &[&str], options: &MergeOptions, mut write: W) -> Result<(), Error>
+where
+    W: FnMut(&[SolanaPriceEntry]) -> Result<(), Error>,
+{
+    let parse_options = ParseOptions {
+        price_scale: options.format.scale,
+        rounding: options.format.rounding,
+    };
+    let mut checksums = ChecksumSummary::default();
+
+    let mut files = Vec::new();
+    for dir in parse_files.iter() {
+        let found = fetch_files_alphabetically(dir)?;
+        println!("Files found: {}", found.len());
+        files.extend(found);
+    }
+
+    let mut high_water = None;
+    let mut total_dropped = 0;
+
+    let work = |file: &String| parse_source(file, &parse_options, get_vector);
+
+    ordered_parallel(&files, options.jobs, work, parsed_bytes, |file, parsed| {
+        let ParsedFile { status, mut data, .. } = parsed.inspect_err(|err| {
+            eprintln!("Error processing file {}: {}", file, err);
+        })?;
+        if !accept_checksum(file, status, options.checksum_policy, &mut checksums)? {
+            return Ok(());
+        }
+
+        println!("Total entries in file {:?}: {}", filename(file), data.len());
+        let dropped = drop_overlap(&mut data, &mut high_water);
+        if dropped > 0 {
+            println!("Dropped {} duplicate entries from {:?}", dropped, filename(file));
+            total_dropped += dropped;
+        }
+        write(&data)
+    })?;
+
+    checksums.print();
+    println!("Duplicate entries dropped: {}", total_dropped);
+    Ok(())
+}
+
+/** Keeps only entries strictly after the high-water mark, advancing it as it goes. */
+fn drop_overlap<R: Record>(data: &mut Vec<R>, high_water: &mut Option<u32>) -> usize {
+    let before = data.len();
+    data.retain(|entry| {
+        if high_water.is_some_and(|mark| entry.time() <= mark) {
+            return false;
+        }
+        *high_water = Some(entry.time());
+        true
+    });
+    before - data.len()
+}
+
+/** What `write_to_file` does when the incoming entries don't start after the file's last record. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlapPolicy {
+    #[default]
+    Error,            // Refuse the write.
+    SkipOverlap,      // Drop incoming entries at or before the file's last record.
+    OverwriteOverlap, // Replace the stored records from the first to the last incoming timestamp, keep the ones after.
+}
+
+impl FromStr for OverlapPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<OverlapPolicy, String> {
+        match name {
+            "error" => Ok(OverlapPolicy::Error),
+            "skip" => Ok(OverlapPolicy::SkipOverlap),
+            "overwrite" => Ok(OverlapPolicy::OverwriteOverlap),
+            _ => Err(format!("Unknown overlap policy {:?}, use error, skip or overwrite", name)),
+        }
+    }
+}
+
+// Every layout starts its records with the u32 timestamp.
+fn read_time(file: &mut File, (offset, record_size): (u64, u64), index: u64) -> Result<u32, Error> {
+    let mut buffer = [0u8; 4];
+    file.seek(SeekFrom::Start(offset + index * record_size))?;
+    file.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+// Index of the first record at or after `time` in a file of `count` sorted records.
+fn lower_bound_in_file(file: &mut File, geometry: (u64, u64), count: u64, time: u32) -> Result<u64, Error> {
+    let (mut lo, mut hi) = (0, count);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if read_time(file, geometry, mid)? < time {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(lo)
+}
+
+pub fn write_to_file(path: &str, vec: &[SolanaPriceEntry], format: PriceFormat, policy: OverlapPolicy) -> Result<(), Error> {
+    write_records(path, vec, format, policy).map(|_| ())
+}
+
+pub fn write_ohlcv_to_file(path: &str, vec: &[OhlcvEntry], format: PriceFormat, policy: OverlapPolicy) -> Result<(), Error> {
+    write_records(path, vec, format, policy).map(|_| ())
+}
+
+/**
+ * Appends records of one layout and price format, creating the file with a header if needed.
+ * Returns how many were appended, fewer than given when `SkipOverlap` dropped some.
+ */
+pub fn write_records<R: Record>(path: &str, vec: &[R], format: PriceFormat, policy: OverlapPolicy) -> Result<u64, Error> {
+    append_records(path, path, vec, format, policy)
+}
+
+// Errors name `name` instead of `path`, merges append to a scratch copy of the output.
+fn append_records<R: Record>(path: &str, name: &str, vec: &[R], format: PriceFormat, policy: OverlapPolicy) -> Result<u64, Error> {
+    format.validate()?;
+
+    // Refuse before touching the file rather than wrapping prices.
+    if let Some(entry) = vec.iter().find(|entry| entry.max_price() > format.width.max_value()) {
+        return Err(Error::Format(format!(
+            "Price {} at {} doesn't fit {:?} storage at {} decimals, use PriceWidth::U64",
+            entry.max_price(), format_time(entry.time()), format.width, format.scale
+        )));
+    }
+
+    // Lookups and the overlap policies rely on strictly increasing timestamps.
+    if let Some(pair) = vec.windows(2).find(|pair| pair[1].time() <= pair[0].time()) {
+        return Err(Error::Timeline { path: name.to_string(), last: pair[0].time(), next: pair[1].time() });
+    }
 
-pub fn write_to_file(path: &str, vec: &[SolanaPriceEntry]) -> Result<(), Box<dyn Error>> {
     let file_path = Path::new(path);
 
-    // Check if file exists and validate timeline.
-    if file_path.exists() {
-        // Read first record from existing binary file to validate timeline.
-        let file = File::open(file_path)?;
-        let mut reader = BufReader::new(file);
-        let mut buffer = [0u8; 8]; // 8 bytes for each u32
-        
-        if reader.read_exact(&mut buffer).is_ok() {
-            let csv_timestamp = u32::from_le_bytes([
-                buffer[0], buffer[1], buffer[2], buffer[3]
-            ]);
-            
-            if let Some(first_entry) = vec.first() {
-                let vec_timestamp = first_entry.time;
-                if csv_timestamp > vec_timestamp {
-                    // Convert to human readable (assuming seconds since epoch).
-                    let csv_datetime = chrono::DateTime::from_timestamp(csv_timestamp as i64, 0)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                        .unwrap_or_else(|| csv_timestamp.to_string());
-                    let vec_datetime = chrono::DateTime::from_timestamp(vec_timestamp as i64, 0)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                        .unwrap_or_else(|| vec_timestamp.to_string());
-                        
-                    return Err(format!(
-                        "Timeline validation failed:\n  File starts at:   {} ({})\n  Vector starts at: {} ({})\nFile should start before or at the vector timestamp.",
-                        csv_timestamp, csv_datetime,
-                        vec_timestamp, vec_datetime
-                    ).into());
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(file_path)?;
+
+    // New files get a header, legacy headerless files keep their layout.
+    let mut header = if file.metadata()?.len() == 0 {
+        let header = FileHeader {
+            layout: R::LAYOUT,
+            price_scale: format.scale,
+            price_width: format.width,
+            rounding: format.rounding,
+            ..FileHeader::default()
+        };
+        write_header(&mut file, &header)?;
+        Some(header)
+    } else {
+        read_header(&mut file)?
+    };
+
+    let layout = header.as_ref().map_or(RecordLayout::Close, |header| header.layout);
+    if layout != R::LAYOUT {
+        return Err(Error::Format(format!("{} holds {:?} records, can't append {:?} records", name, layout, R::LAYOUT)));
+    }
+
+    let stored_format = header.as_ref().map_or(PriceFormat::LEGACY, |header| header.format());
+    if stored_format != format {
+        return Err(Error::Format(format!("{} stores prices as {}, can't append {}", name, stored_format, format)));
+    }
+
+    let geometry = record_geometry(header.as_ref());
+    let (offset, record_size) = geometry;
+    let payload = file.metadata()?.len() - offset;
+    if payload % record_size != 0 {
+        return Err(Error::Format(format!(
+            "{} ends in a partial record ({} stray bytes), repair it before appending",
+            name, payload % record_size
+        )));
+    }
+
+    let count = payload / record_size;
+    let mut vec = vec;
+
+    // Validate the timeline against the file's tail record.
+    if let (Some(last_index), Some(first_entry)) = (count.checked_sub(1), vec.first()) {
+        let file_timestamp = read_time(&mut file, geometry, last_index)?;
+        let vec_timestamp = first_entry.time();
+
+        if file_timestamp >= vec_timestamp {
+            match policy {
+                OverlapPolicy::Error => {
+                    return Err(Error::Timeline { path: name.to_string(), last: file_timestamp, next: vec_timestamp });
+                }
+                OverlapPolicy::SkipOverlap => {
+                    let keep = vec.partition_point(|entry| entry.time() <= file_timestamp);
+                    vec = &vec[keep..];
+                }
+                OverlapPolicy::OverwriteOverlap => {
+                    overwrite_range(&mut file, geometry, count, vec, format.width, header.as_mut())?;
+                    return Ok(vec.len() as u64);
                 }
             }
         }
     }
 
     // Append binary data to the file.
-    let file = fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(file_path)?;
-    
-    let mut writer = BufWriter::new(file);
+    file.seek(SeekFrom::End(0))?;
+    let mut writer = BufWriter::new(&file);
 
-    for &pe in vec {
-        // Write each u32 as 8 bytes in little-endian format
-        writer.write_all(&pe.time.to_le_bytes())?;
-        writer.write_all(&pe.close_price.to_le_bytes())?;
+    for record in vec {
+        record.write_le(&mut writer, format.width)?;
     }
 
     writer.flush()?;
+    drop(writer);
+
+    // Keep the header in step with the records.
+    if let Some(header) = header.as_mut() {
+        if let (Some(first), Some(last)) = (vec.first(), vec.last()) {
+            if header.record_count == 0 {
+                header.first_time = first.time();
+            }
+            header.last_time = last.time();
+            header.record_count += vec.len() as u64;
+        }
+        if header.record_count == 0 {
+            header.first_time = 0;
+            header.last_time = 0;
+        }
+        write_header(&mut file, header)?;
+    }
+
+    Ok(vec.len() as u64)
+}
+
+// Bytes moved at a time when records after an overwritten range shift along.
+const MOVE_CHUNK: u64 = 1 << 20;
+
+/**
+ * Replaces the `count` stored records from the first incoming timestamp to the last one with `vec`, keeping the
+ * records after it. They move along by however many records the range grows or shrinks by.
+ */
+fn overwrite_range<R: Record>(
+    file: &mut File,
+    geometry: (u64, u64),
+    count: u64,
+    vec: &[R],
+    width: PriceWidth,
+    header: Option<&mut FileHeader>,
+) -> Result<(), Error> {
+    let (Some(first), Some(last)) = (vec.first(), vec.last()) else {
+        return Ok(());
+    };
+    let (offset, record_size) = geometry;
+    let start = lower_bound_in_file(file, geometry, count, first.time())?;
+    let end = match last.time().checked_add(1) {
+        Some(after) => lower_bound_in_file(file, geometry, count, after)?,
+        None => count,
+    };
+    let kept = count - end;
+    let new_end = start + vec.len() as u64;
+
+    move_bytes(file, offset + end * record_size, offset + new_end * record_size, kept * record_size)?;
+    file.seek(SeekFrom::Start(offset + start * record_size))?;
+    let mut writer = BufWriter::new(&*file);
+    for record in vec {
+        record.write_le(&mut writer, width)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    file.set_len(offset + (new_end + kept) * record_size)?;
+
+    if let Some(header) = header {
+        header.record_count = new_end + kept;
+        if start == 0 {
+            header.first_time = first.time();
+        }
+        if kept == 0 {
+            header.last_time = last.time();
+        }
+        write_header(file, header)?;
+    }
+    Ok(())
+}
+
+// Moves `len` bytes from `from` to `to`, back to front when moving towards the end so nothing is overwritten unread.
+fn move_bytes(file: &mut File, from: u64, to: u64, len: u64) -> Result<(), Error> {
+    if from == to {
+        return Ok(());
+    }
+    let mut buffer = vec![0u8; MOVE_CHUNK.min(len) as usize];
+    let mut done = 0;
+    while done < len {
+        let size = (len - done).min(MOVE_CHUNK);
+        let at = if to > from { len - done - size } else { done };
+        let chunk = &mut buffer[..size as usize];
+        file.seek(SeekFrom::Start(from + at))?;
+        file.read_exact(chunk)?;
+        file.seek(SeekFrom::Start(to + at))?;
+        file.write_all(chunk)?;
+        done += size;
+    }
+    Ok(())
+}
+
+/** Refuses a file whose payload isn't whole records, or with `repair` cuts the torn tail off. */
+pub(crate) fn check_torn_tail(path: &str, repair: bool) -> Result<(), Error> {
+    let mut file = File::open(path)?;
+    let header = read_header_unchecked(&mut file)?;
+    let (offset, record_size) = record_geometry(header.as_ref());
+
+    let payload = file.metadata()?.len().saturating_sub(offset);
+    let stored = payload / record_size;
+    let torn = payload % record_size;
+    let stale_header = header.is_some_and(|header| header.record_count != stored);
+
+    if torn == 0 && !stale_header {
+        return Ok(());
+    }
+    if !repair {
+        return Err(Error::Format(format!(
+            "{} has {} stray bytes after its last whole record{}, it was likely cut off mid-write. Rerun in repair mode to truncate the tail.",
+            path, torn, if stale_header { " and a stale header" } else { "" }
+        )));
+    }
+
+    println!("Repairing {}: dropping {} stray bytes, keeping {} records", path, torn, stored);
+    truncate_records(path, stored)
+}
+
+/** Number of whole records in the file, without validating the header count. */
+pub(crate) fn stored_records(path: &str) -> Result<u64, Error> {
+    let mut file = File::open(path)?;
+    let (offset, record_size) = record_geometry(read_header_unchecked(&mut file)?.as_ref());
+    Ok(file.metadata()?.len().saturating_sub(offset) / record_size)
+}
+
+/** Cuts the file back to its first `count` records and brings the header in line. */
+pub(crate) fn truncate_records(path: &str, count: u64) -> Result<(), Error> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let mut header = read_header_unchecked(&mut file)?;
+    let geometry = record_geometry(header.as_ref());
+    let (offset, record_size) = geometry;
+
+    let stored = file.metadata()?.len().saturating_sub(offset) / record_size;
+    if count > stored {
+        return Err(Error::Format(format!("Can't truncate {} to {} records, it only holds {}", path, count, stored)));
+    }
+
+    file.set_len(offset + count * record_size)?;
+
+    if let Some(header) = header.as_mut() {
+        header.record_count = count;
+        match count.checked_sub(1) {
+            Some(last_index) => {
+                header.first_time = read_time(&mut file, geometry, 0)?;
+                header.last_time = read_time(&mut file, geometry, last_index)?;
+            }
+            None => {
+                header.first_time = 0;
+                header.last_time = 0;
+            }
+        }
+        write_header(&mut file, header)?;
+    }
+
+    file.sync_all()?;
     Ok(())
 }
 
 // Helper function to read binary data back (for testing/verification).
-pub fn read_binary_file(path: &str) -> Result<Vec<(u32, u32)>, Box<dyn Error>> {
-    let file = File::open(path)?;
+pub fn read_binary_file(path: &str) -> Result<Vec<(u32, u64)>, Error> {
+    let mut file = File::open(path)?;
+
+    // Validates the header if there is one, legacy files are read from the start.
+    let header = read_header(&mut file)?;
+    let layout = header.as_ref().map_or(RecordLayout::Close, |header| header.layout);
+    let width = header.as_ref().map_or(PriceWidth::U32, |header| header.price_width);
+
     let mut reader = BufReader::new(file);
-    let mut vec = Vec::new();
-    let mut buffer = [0u8; 8]; // 4 bytes for time (u32) + 4 bytes for price (u32).
+    let mut vec = Vec::with_capacity(header.map_or(0, |h| h.record_count as usize));
+    let mut buffer = vec![0u8; layout.record_size(width)]; // 4 bytes for time (u32), close price further in.
 
     while reader.read_exact(&mut buffer).is_ok() {
-        let time = u32::from_le_bytes([
-            buffer[0], buffer[1], buffer[2], buffer[3]
-        ]);
-        let price = u32::from_le_bytes([
-            buffer[4], buffer[5], buffer[6], buffer[7],            
-        ]);        
-        vec.push((time, price));
+        let entry = SolanaPriceEntry::from_record(&buffer, layout, width);
+        vec.push((entry.time, entry.close_price));
     }
 
     Ok(vec)
 }
 
-pub fn sample_readouts() -> Result<(), Box<dyn Error>> {
-    let readout = read_binary_file("/Users/wincentdulkowski/Files/0_Projects/rebalancing-data/solana_historical_price.dat").or_else(|err| {
-        eprintln!("Error reading directory: {}", err);
-        Err(err)
-    })?;            
-    
-    let line_count = readout.len();
+/** Reads a whole OHLCV layout file back, see `PriceReader` for random access. */
+pub fn read_ohlcv_file(path: &str) -> Result<Vec<OhlcvEntry>, Error> {
+    let mut file = File::open(path)?;
 
-    // Print first 50.
-    println!("\nFirst 50 entries:");
-    for entry in readout.iter().take(50) {
-        println!("{:?}", entry);
+    let header = read_header(&mut file)?
+        .filter(|header| header.layout == RecordLayout::Ohlcv)
+        .ok_or_else(|| Error::Format(format!("{} is not an OHLCV file", path)))?;
+
+    let mut reader = BufReader::new(file);
+    let mut vec = Vec::with_capacity(header.record_count as usize);
+    let mut buffer = vec![0u8; header.record_size()];
+
+    while reader.read_exact(&mut buffer).is_ok() {
+        vec.push(OhlcvEntry::from_le_bytes(&buffer, header.price_width));
     }
 
-    // Print middle 50.
-    println!("\nMid 50 entries:");
-    for entry in readout.iter().skip(line_count / 2).take(50) {
-        println!("{:?}", entry);
+    Ok(vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prep::atomic::test_path;
+
+    fn entries(times: impl IntoIterator<Item = u32>, price: u64) -> Vec<SolanaPriceEntry> {
+        times.into_iter().map(|time| SolanaPriceEntry { time, close_price: price }).collect()
     }
-    // Print last 50.
-    println!("\nLast 50 entries:");
-    for entry in readout.iter().rev().take(50) {
-        println!("{:?}", entry);
+
+    fn stored(path: &str) -> Vec<(u32, u64)> {
+        let reader = PriceReader::open(path).unwrap();
+        let header = reader.header().unwrap();
+        let records = read_binary_file(path).unwrap();
+        assert_eq!(header.record_count, records.len() as u64);
+        assert_eq!(header.first_time, records.first().map_or(0, |record| record.0));
+        assert_eq!(header.last_time, records.last().map_or(0, |record| record.0));
+        records
     }
 
-    Ok(())
+    // Writes `before` with price 1, then `incoming` with price 2 under `policy`.
+    fn overlap(name: &str, before: &[u32], incoming: &[u32], policy: OverlapPolicy) -> (Result<u64, Error>, Vec<(u32, u64)>) {
+        let path = test_path(&format!("merge-{}", name));
+        write_records(&path, &entries(before.iter().copied(), 1), PriceFormat::default(), OverlapPolicy::Error).unwrap();
+        let result = write_records(&path, &entries(incoming.iter().copied(), 2), PriceFormat::default(), policy);
+        let records = stored(&path);
+        fs::remove_file(&path).unwrap();
+        (result, records)
+    }
+
+    #[test]
+    fn error_policy_refuses_overlap() {
+        let (result, records) = overlap("error", &[1, 2, 3, 4, 5], &[3, 4, 6], OverlapPolicy::Error);
+        assert!(matches!(result, Err(Error::Timeline { last: 5, next: 3, .. })));
+        assert_eq!(records, [(1, 1), (2, 1), (3, 1), (4, 1), (5, 1)]);
+    }
+
+    #[test]
+    fn skip_policy_drops_overlapping_entries() {
+        let (result, records) = overlap("skip", &[1, 2, 3, 4, 5], &[3, 5, 6, 7], OverlapPolicy::SkipOverlap);
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(records, [(1, 1), (2, 1), (3, 1), (4, 1), (5, 1), (6, 2), (7, 2)]);
+
+        let (result, records) = overlap("skip-all", &[1, 2, 3], &[1, 3], OverlapPolicy::SkipOverlap);
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(records, [(1, 1), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn overwrite_policy_replaces_only_the_incoming_span() {
+        // Same length, a stored record missing from the incoming span goes with it.
+        let (result, records) = overlap("overwrite-middle", &[1, 2, 3, 4, 5, 6], &[2, 4, 5], OverlapPolicy::OverwriteOverlap);
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(records, [(1, 1), (2, 2), (4, 2), (5, 2), (6, 1)]);
+
+        // Grows, the records after the span move towards the end.
+        let (_, records) = overlap("overwrite-grow", &[1, 2, 10, 11], &[2, 3, 4, 5], OverlapPolicy::OverwriteOverlap);
+        assert_eq!(records, [(1, 1), (2, 2), (3, 2), (4, 2), (5, 2), (10, 1), (11, 1)]);
+
+        // From the first record, and past the last one.
+        let (_, records) = overlap("overwrite-start", &[3, 4, 5], &[1, 2, 3], OverlapPolicy::OverwriteOverlap);
+        assert_eq!(records, [(1, 2), (2, 2), (3, 2), (4, 1), (5, 1)]);
+        let (_, records) = overlap("overwrite-end", &[1, 2, 3], &[2, 4], OverlapPolicy::OverwriteOverlap);
+        assert_eq!(records, [(1, 1), (2, 2), (4, 2)]);
+    }
+
+    #[test]
+    fn unsorted_input_is_refused_before_writing() {
+        let path = test_path("merge-unsorted");
+        for times in [[1, 3, 2], [1, 2, 2]] {
+            let result = write_records(&path, &entries(times, 1), PriceFormat::default(), OverlapPolicy::SkipOverlap);
+            assert!(matches!(result, Err(Error::Timeline { last: 3 | 2, next: 2, .. })));
+        }
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn overwrite_moves_large_tails_both_ways() {
+        // A tail of several `MOVE_CHUNK`s, moved towards the end and then back.
+        let before: Vec<u32> = (0..400_000).map(|index| index * 2).collect();
+        let grow: Vec<u32> = (0..100).collect();
+        let (_, records) = overlap("overwrite-large-grow", &before, &grow, OverlapPolicy::OverwriteOverlap);
+        assert_eq!(records.len(), 400_000 - 50 + 100);
+        assert!(records[..100].iter().enumerate().all(|(index, &record)| record == (index as u32, 2)));
+        assert!(records[100..].iter().zip(50..).all(|(&record, index)| record == (index * 2, 1)));
+
+        let shrink = [0, 1_000];
+        let (_, records) = overlap("overwrite-large-shrink", &before, &shrink, OverlapPolicy::OverwriteOverlap);
+        assert_eq!(records.len(), 400_000 - 501 + 2);
+        assert_eq!(records[..3], [(0, 2), (1_000, 2), (1_002, 1)]);
+        assert_eq!(records.last(), Some(&(799_998, 1)));
+    }
+
+    #[test]
+    fn failed_merges_name_the_output_and_remove_the_scratch_copy() {
+        let dir = test_path("merge-sources");
+        for (folder, start) in [("a", 1_700_000_000), ("b", 1_700_000_001)] {
+            fs::create_dir_all(format!("{}/{}", dir, folder)).unwrap();
+            let rows: String = (start..start + 3).map(|time| format!("{}000,1,1,1,2.5,1,0,1,1,1,1,0\n", time)).collect();
+            fs::write(format!("{}/{}/SOLUSDT-1s-{}.csv", dir, folder, folder), rows).unwrap();
+        }
+        let dest = format!("{}/out.dat", dir);
+        let (a, b) = (format!("{}/a", dir), format!("{}/b", dir));
+        parse_binance(&dest, &[&a], &MergeOptions::default()).unwrap();
+
+        let refuse = MergeOptions { overlap_policy: OverlapPolicy::Error, ..MergeOptions::default() };
+        match parse_binance(&dest, &[&b], &refuse) {
+            Err(Error::Timeline { path, .. }) => assert_eq!(path, dest),
+            other => panic!("expected a timeline error, got {:?}", other),
+        }
+        assert!(!Path::new(&temp_path(&dest)).exists());
+
+        let wider = MergeOptions { format: PriceFormat { scale: 4, ..PriceFormat::default() }, ..MergeOptions::default() };
+        match parse_binance(&dest, &[&b], &wider) {
+            Err(Error::Format(message)) => {
+                assert!(message.starts_with(&format!("{} stores prices as 3 decimals in 4 bytes", dest)), "{}", message)
+            }
+            other => panic!("expected a format error, got {:?}", other),
+        }
+        assert!(!Path::new(&temp_path(&dest)).exists());
+        assert_eq!(stored(&dest).len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
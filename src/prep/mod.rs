@@ -1,2 +1,20 @@
+mod atomic;
+pub mod checksum;
+pub mod decimal;
+pub mod delta;
+pub mod densify;
+pub mod error;
+pub mod export;
+pub mod frames;
+pub mod gaps;
+pub mod header;
+pub mod inspect;
+pub mod ipc;
+pub mod kline;
+pub mod manifest;
 pub mod merge;
-pub use merge::*;
\ No newline at end of file
+mod parallel;
+pub mod reader;
+pub mod record;
+pub mod resample;
+pub mod sqlite;
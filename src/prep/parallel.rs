@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Condvar, Mutex,
+    },
+    thread,
+};
+
+use crate::prep::error::Error;
+
+// Workers for `jobs = 0`, more cores only add parsed files waiting for the single writer.
+const MAX_DEFAULT_JOBS: usize = 8;
+
+// Bytes of results held for the writer before workers stop starting new items.
+const QUEUE_BYTES: usize = 1 << 30;
+
+/** Worker count for `jobs`, where 0 means one per CPU core, up to `MAX_DEFAULT_JOBS`. */
+pub(crate) fn worker_count(jobs: usize) -> usize {
+    if jobs > 0 {
+        return jobs;
+    }
+    thread::available_parallelism().map_or(1, |cores| cores.get().min(MAX_DEFAULT_JOBS))
+}
+
+/** Items handed to the writer and bytes of results waiting for it. */
+#[derive(Default)]
+struct Progress {
+    consumed: usize,
+    held: usize,
+}
+
+/** Stops the workers on a panic, so the scope ends and passes the panic on instead of waiting on them forever. */
+struct AbortOnPanic<'a> {
+    abort: &'a AtomicBool,
+    state: &'a Mutex<Progress>,
+    progress: &'a Condvar,
+}
+
+impl Drop for AbortOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            let state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+            self.abort.store(true, Ordering::SeqCst);
+            drop(state);
+            self.progress.notify_all();
+        }
+    }
+}
+
+/**
+ * Runs `work` over `items` on up to `jobs` threads and hands each result to `consume` in input order.
+ * Workers stay at most `2 * jobs` items ahead of `consume`, and once `QUEUE_BYTES` of results are held,
+ * as measured by `weigh`, only the item `consume` waits for is started. An error from `consume` stops the
+ * workers and is returned, a panic in `work` or `consume` is passed on.
+ */
+pub(crate) fn ordered_parallel<T, U, W, S, C>(items: &[T], jobs: usize, work: W, weigh: S, mut consume: C) -> Result<(), Error>
+where
+    T: Sync,
+    U: Send,
+    W: Fn(&T) -> U + Sync,
+    S: Fn(&U) -> usize + Sync,
+    C: FnMut(&T, U) -> Result<(), Error>,
+{
+    let jobs = worker_count(jobs).min(items.len()).max(1);
+    let window = 2 * jobs;
+
+    let next = AtomicUsize::new(0);
+    let abort = AtomicBool::new(false);
+    let state = Mutex::new(Progress::default());
+    let progress = Condvar::new();
+    let (sender, receiver) = mpsc::sync_channel::<(usize, U, usize)>(window);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (next, abort, state, progress, work, weigh) = (&next, &abort, &state, &progress, &work, &weigh);
+
+            scope.spawn(move || {
+                let _guard = AbortOnPanic { abort, state, progress };
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= items.len() {
+                        break;
+                    }
+
+                    // Wait until the writer has caught up, otherwise one slow file lets the rest pile up.
+                    // The item it waits for always goes ahead, whatever is held.
+                    let mut done = state.lock().unwrap_or_else(|err| err.into_inner());
+                    while (index >= done.consumed + window || (done.held >= QUEUE_BYTES && index > done.consumed))
+                        && !abort.load(Ordering::SeqCst)
+                    {
+                        done = progress.wait(done).unwrap_or_else(|err| err.into_inner());
+                    }
+                    drop(done);
+                    if abort.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    let result = work(&items[index]);
+                    let bytes = weigh(&result);
+                    state.lock().unwrap_or_else(|err| err.into_inner()).held += bytes;
+                    if sender.send((index, result, bytes)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        let _guard = AbortOnPanic { abort: &abort, state: &state, progress: &progress };
+
+        // Results arrive in any order, hold them back until it's their turn.
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        let mut outcome = Ok(());
+
+        'receive: for (index, result, bytes) in receiver.iter() {
+            pending.insert(index, (result, bytes));
+            while let Some((result, bytes)) = pending.remove(&expected) {
+                if let Err(err) = consume(&items[expected], result) {
+                    outcome = Err(err);
+                    break 'receive;
+                }
+                expected += 1;
+                let mut done = state.lock().unwrap_or_else(|err| err.into_inner());
+                done.consumed = expected;
+                done.held -= bytes;
+                drop(done);
+                progress.notify_all();
+            }
+        }
+
+        if outcome.is_err() {
+            // Wake waiting workers and fail their sends, the scope only ends once they're out.
+            let done = state.lock().unwrap_or_else(|err| err.into_inner());
+            abort.store(true, Ordering::SeqCst);
+            drop(done);
+            progress.notify_all();
+            drop(receiver);
+        }
+        outcome
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_arrive_in_order() {
+        let items: Vec<u64> = (0..200).collect();
+        let mut seen = Vec::new();
+        // Later items finish first, the writer still sees them in order.
+        let work = |&item: &u64| {
+            thread::sleep(std::time::Duration::from_micros((200 - item) * 10));
+            item * 2
+        };
+        ordered_parallel(&items, 8, work, |_| 1, |&item, result| {
+            assert_eq!(result, item * 2);
+            seen.push(item);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, items);
+    }
+
+    #[test]
+    fn error_stops_the_workers() {
+        let items: Vec<u32> = (0..1000).collect();
+        let started = AtomicUsize::new(0);
+        let work = |_: &u32| started.fetch_add(1, Ordering::SeqCst);
+        let result = ordered_parallel(&items, 4, work, |_| 0, |&item, _| match item {
+            10 => Err(Error::Format("stop".to_string())),
+            _ => Ok(()),
+        });
+        assert!(matches!(result, Err(Error::Format(_))));
+        assert!(started.load(Ordering::SeqCst) < items.len());
+    }
+
+    #[test]
+    fn held_bytes_hold_back_new_items() {
+        let items: Vec<u32> = (0..64).collect();
+        let running = AtomicUsize::new(0);
+        let work = |_: &u32| running.fetch_add(1, Ordering::SeqCst);
+        // Every result is over the budget, so nothing starts ahead of the item being written.
+        ordered_parallel(&items, 8, work, |_| QUEUE_BYTES, |&item, started| {
+            assert!(started <= item as usize + 8, "item {} started as number {}", item, started);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn panic_in_work_is_passed_on() {
+        let items: Vec<u32> = (0..100).collect();
+        let outcome = std::panic::catch_unwind(|| {
+            let work = |&item: &u32| {
+                if item == 3 {
+                    panic!("bad item");
+                }
+                item
+            };
+            ordered_parallel(&items, 4, work, |_| 0, |_, _| Ok(()))
+        });
+        assert!(outcome.is_err());
+    }
+
+    #[test]
+    fn panic_in_consume_is_passed_on() {
+        let items: Vec<u32> = (0..100).collect();
+        let outcome = std::panic::catch_unwind(|| {
+            ordered_parallel(&items, 4, |&item| item, |_| 0, |&item, _| {
+                assert_ne!(item, 3);
+                Ok(())
+            })
+        });
+        assert!(outcome.is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    iter::FusedIterator,
+    marker::PhantomData,
+};
+
+use memmap2::Mmap;
+
+use crate::prep::atomic::{commit_temp, temp_path};
+use crate::prep::delta;
+use crate::prep::error::Error;
+use crate::prep::frames;
+use crate::prep::header::{read_header_unchecked, record_geometry, Encoding, FileHeader, PriceFormat, PriceWidth, RecordLayout};
+use crate::prep::record::{OhlcvEntry, Record, SolanaPriceEntry};
+
+/** Where an encoded block or frame sits in the file and what its index says about it. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Block {
+    pub offset: usize,
+    pub end: usize,
+    pub first_time: u32,
+    pub first_price: u64, // Delta blocks start from it, zstd frames leave it 0.
+    pub count: usize,
+}
+
+/** How the records are laid out after the header. */
+enum Source {
+    Raw { offset: usize }, // Fixed-width records from `offset` to the end.
+    Blocks { encoding: Encoding, blocks: Vec<Block>, block_records: usize }, // Each decodes to raw records.
+}
+
+/**
+ * Random access over a merged `.dat` file without loading it into memory.
+ * Raw files are read straight from the mapping, delta and zstd encoded ones decode only the blocks a lookup or window touches.
+ */
+pub struct PriceReader {
+    mmap: Mmap,
+    header: Option<FileHeader>,
+    layout: RecordLayout,
+    width: PriceWidth,
+    record_size: usize,
+    len: usize,
+    source: Source,
+}
+
+impl PriceReader {
+    pub fn open(path: &str) -> Result<PriceReader, Error> {
+        let mut file = File::open(path)?;
+
+        // Legacy files have records from byte zero.
+        let header = read_header_unchecked(&mut file)?;
+        let encoding = header.as_ref().map_or(Encoding::Raw, |header| header.encoding);
+        let layout = header.as_ref().map_or(RecordLayout::Close, |header| header.layout);
+        let width = header.as_ref().map_or(PriceWidth::U32, |header| header.price_width);
+        let (offset, record_size) = record_geometry(header.as_ref());
+        let (offset, record_size) = (offset as usize, record_size as usize);
+
+        // Safety: the file is only read, concurrent writers would make the view inconsistent.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let (source, len) = match header.as_ref().filter(|_| encoding != Encoding::Raw) {
+            None => {
+                if let Some(header) = &header {
+                    header.validate(mmap.len() as u64)?;
+                }
+                let payload = mmap.len() - offset;
+                if payload % record_size != 0 {
+                    return Err(Error::Format(format!(
+                        "File {} has {} payload bytes, not a multiple of the {} byte record size",
+                        path, payload, record_size
+                    )));
+                }
+                (Source::Raw { offset }, payload / record_size)
+            }
+            Some(header) => {
+                let (blocks, block_records) = match encoding {
+                    Encoding::Delta if layout != RecordLayout::Close => {
+                        return Err(Error::Format(format!("{} is delta encoded but holds {:?} records", path, layout)));
+                    }
+                    Encoding::Delta => delta::read_block_index(&mmap, path)?,
+                    _ => frames::read_frame_index(&mmap, path)?,
+                };
+                check_blocks(path, header, &blocks, block_records)?;
+                (Source::Blocks { encoding, blocks, block_records }, header.record_count as usize)
+            }
+        };
+
+        Ok(PriceReader { mmap, header, layout, width, record_size, len, source })
+    }
+
+    /** `None` for legacy headerless files. */
+    pub fn header(&self) -> Option<&FileHeader> {
+        self.header.as_ref()
+    }
+
+    /** Header for a raw copy of the records, what `densify`, `resample` and decoding start from. */
+    pub fn raw_header(&self) -> FileHeader {
+        // Legacy files were truncated to 3 decimals.
+        let header = self.header.clone().unwrap_or_else(|| FileHeader {
+            rounding: PriceFormat::LEGACY.rounding,
+            ..FileHeader::default()
+        });
+        FileHeader { encoding: Encoding::Raw, ..header }
+    }
+
+    pub fn layout(&self) -> RecordLayout {
+        self.layout
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        match self.source {
+            Source::Raw { .. } => Encoding::Raw,
+            Source::Blocks { encoding, .. } => encoding,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /** Blocks or frames of an encoded file, 0 for raw ones. */
+    pub fn block_count(&self) -> usize {
+        match &self.source {
+            Source::Raw { .. } => 0,
+            Source::Blocks { blocks, .. } => blocks.len(),
+        }
+    }
+
+    /** Raw records of block `block` of an encoded file, decoded or decompressed. */
+    fn decode(&self, block: usize) -> Result<Vec<u8>, Error> {
+        let Source::Blocks { encoding, blocks, .. } = &self.source else {
+            return Err(Error::Format("Raw files have no blocks".to_string()));
+        };
+        let entry = &blocks[block];
+        let bytes = &self.mmap[entry.offset..entry.end];
+        let records = match encoding {
+            Encoding::Delta => delta::decode_block(bytes, entry, self.width)?,
+            _ => frames::decompress_frame(bytes, entry, self.record_size, self.len)?,
+        };
+        let size = entry.count * self.record_size;
+        if records.len() != size {
+            return Err(Error::Format(format!("Block {} decodes to {} bytes instead of {}", block, records.len(), size)));
+        }
+        Ok(records)
+    }
+
+    /** Calls `read` with the bytes of the record at `index`, decoding its block if need be. */
+    fn with_record<T>(&self, index: usize, read: impl FnOnce(&[u8]) -> T) -> Result<Option<T>, Error> {
+        if index >= self.len {
+            return Ok(None);
+        }
+        match &self.source {
+            Source::Raw { offset } => {
+                let start = offset + index * self.record_size;
+                Ok(Some(read(&self.mmap[start..start + self.record_size])))
+            }
+            Source::Blocks { block_records, .. } => {
+                let records = self.decode(index / block_records)?;
+                let start = index % block_records * self.record_size;
+                Ok(Some(read(&records[start..start + self.record_size])))
+            }
+        }
+    }
+
+    /** Time and close of a record, whatever the layout. */
+    pub fn get(&self, index: usize) -> Result<Option<SolanaPriceEntry>, Error> {
+        self.with_record(index, |bytes| SolanaPriceEntry::from_record(bytes, self.layout, self.width))
+    }
+
+    /** The full kline, `None` unless the file uses the OHLCV layout. */
+    pub fn get_ohlcv(&self, index: usize) -> Result<Option<OhlcvEntry>, Error> {
+        if self.layout != RecordLayout::Ohlcv {
+            return Ok(None);
+        }
+        self.with_record(index, |bytes| OhlcvEntry::from_le_bytes(bytes, self.width))
+    }
+
+    pub fn first(&self) -> Result<Option<SolanaPriceEntry>, Error> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Result<Option<SolanaPriceEntry>, Error> {
+        match self.len.checked_sub(1) {
+            Some(index) => self.get(index),
+            None => Ok(None),
+        }
+    }
+
+    /** Index of the first record at or after `time`, records must be sorted by time. Encoded files decode one block. */
+    pub fn lower_bound(&self, time: u32) -> Result<usize, Error> {
+        match &self.source {
+            Source::Raw { offset } => Ok(lower_bound_in(&self.mmap[*offset..], self.record_size, time)),
+            Source::Blocks { blocks, block_records, .. } => {
+                // Last block starting at or before `time`, the record is in it or starts the next one.
+                let block = blocks.partition_point(|entry| entry.first_time <= time);
+                if block == 0 {
+                    return Ok(0);
+                }
+                let records = self.decode(block - 1)?;
+                Ok((block - 1) * block_records + lower_bound_in(&records, self.record_size, time))
+            }
+        }
+    }
+
+    /** Close price of the record stamped exactly `time`, if there is one. */
+    pub fn price_at(&self, time: u32) -> Result<Option<u64>, Error> {
+        let index = self.lower_bound(time)?;
+        Ok(self.get(index)?.filter(|entry| entry.time == time).map(|entry| entry.close_price))
+    }
+
+    /** Records with `start <= time < end`. */
+    pub fn range(&self, start: u32, end: u32) -> Result<Entries<'_>, Error> {
+        let from = self.lower_bound(start)?;
+        let to = self.lower_bound(end)?.max(from);
+        Ok(self.slice(from, to))
+    }
+
+    /** Records by index, `from..to`, clamped to the file. */
+    pub fn slice(&self, from: usize, to: usize) -> Entries<'_> {
+        Entries { cursor: Cursor::new(self, from, to) }
+    }
+
+    pub fn iter(&self) -> Entries<'_> {
+        self.slice(0, self.len)
+    }
+
+    /** Whole records by index, `from..to`, clamped to the file. Refused when `R` is for another layout. */
+    pub fn records<R: Record>(&self, from: usize, to: usize) -> Result<Records<'_, R>, Error> {
+        if R::LAYOUT != self.layout {
+            return Err(Error::Format(format!("File holds {:?} records, not {:?}", self.layout, R::LAYOUT)));
+        }
+        Ok(Records { cursor: Cursor::new(self, from, to), record: PhantomData })
+    }
+}
+
+// Index of the first record at or after `time` in a run of sorted fixed-width records.
+fn lower_bound_in(records: &[u8], record_size: usize, time: u32) -> usize {
+    let (mut lo, mut hi) = (0, records.len() / record_size);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let at = mid * record_size;
+        if u32::from_le_bytes([records[at], records[at + 1], records[at + 2], records[at + 3]]) < time {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/** Checks a block or frame index against the header and the file: every block but the last is full, none overlap. */
+fn check_blocks(path: &str, header: &FileHeader, blocks: &[Block], block_records: usize) -> Result<(), Error> {
+    let records: u64 = blocks.iter().map(|block| block.count as u64).sum();
+    if records != header.record_count {
+        return Err(Error::Format(format!(
+            "Header claims {} records but the block index of {} holds {}",
+            header.record_count, path, records
+        )));
+    }
+    for (number, block) in blocks.iter().enumerate() {
+        let full = number + 1 == blocks.len() || block.count == block_records;
+        if block.offset > block.end || block.count == 0 || block.count > block_records || !full {
+            return Err(Error::Format(format!("{} has a block at {} that doesn't fit the file", path, block.offset)));
+        }
+    }
+    Ok(())
+}
+
+/** Walks records by index, keeping the current block of an encoded file decoded. */
+struct Cursor<'a> {
+    reader: &'a PriceReader,
+    next: usize,
+    end: usize,
+    block: Vec<u8>,
+    block_start: usize, // Index of the first record in `block`.
+}
+
+impl<'a> Cursor<'a> {
+    fn new(reader: &'a PriceReader, from: usize, to: usize) -> Cursor<'a> {
+        let end = to.min(reader.len);
+        Cursor { reader, next: from.min(end), end, block: Vec::new(), block_start: 0 }
+    }
+
+    fn next_with<T>(&mut self, read: impl FnOnce(&[u8]) -> T) -> Option<Result<T, Error>> {
+        if self.next >= self.end {
+            return None;
+        }
+        let size = self.reader.record_size;
+        let bytes = match &self.reader.source {
+            Source::Raw { offset } => {
+                let start = offset + self.next * size;
+                &self.reader.mmap[start..start + size]
+            }
+            Source::Blocks { block_records, .. } => {
+                if self.next < self.block_start || self.next >= self.block_start + self.block.len() / size {
+                    let block = self.next / block_records;
+                    match self.reader.decode(block) {
+                        Ok(records) => {
+                            self.block = records;
+                            self.block_start = block * block_records;
+                        }
+                        Err(err) => {
+                            self.next = self.end;
+                            return Some(Err(err));
+                        }
+                    }
+                }
+                let start = (self.next - self.block_start) * size;
+                &self.block[start..start + size]
+            }
+        };
+        self.next += 1;
+        Some(Ok(read(bytes)))
+    }
+
+    fn remaining(&self) -> usize {
+        self.end - self.next
+    }
+}
+
+/** Time and close of a run of records, decoded on the fly. Stops after the first decoding error. */
+pub struct Entries<'a> {
+    cursor: Cursor<'a>,
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Result<SolanaPriceEntry, Error>;
+
+    fn next(&mut self) -> Option<Result<SolanaPriceEntry, Error>> {
+        let (layout, width) = (self.cursor.reader.layout, self.cursor.reader.width);
+        self.cursor.next_with(|bytes| SolanaPriceEntry::from_record(bytes, layout, width))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.cursor.remaining(), Some(self.cursor.remaining()))
+    }
+}
+
+impl ExactSizeIterator for Entries<'_> {}
+
+impl FusedIterator for Entries<'_> {}
+
+/** Whole records in the file's layout, see `PriceReader::records`. */
+pub struct Records<'a, R> {
+    cursor: Cursor<'a>,
+    record: PhantomData<R>,
+}
+
+impl<R: Record> Iterator for Records<'_, R> {
+    type Item = Result<R, Error>;
+
+    fn next(&mut self) -> Option<Result<R, Error>> {
+        let width = self.cursor.reader.width;
+        self.cursor.next_with(|bytes| R::from_le_bytes(bytes, width))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.cursor.remaining(), Some(self.cursor.remaining()))
+    }
+}
+
+impl<R: Record> ExactSizeIterator for Records<'_, R> {}
+
+/**
+ * Writes the records of an encoded file back out as a raw `.dat` with the header it was encoded with.
+ * Blocks are decoded one at a time, returns the number of records.
+ */
+pub(crate) fn decode_to_raw(source: &str, dest: &str, encoding: Encoding) -> Result<u64, Error> {
+    let reader = PriceReader::open(source)?;
+    if reader.encoding() != encoding {
+        return Err(Error::Format(format!("{} holds {:?} encoded records, not {:?}", source, reader.encoding(), encoding)));
+    }
+
+    let temp_file = temp_path(dest);
+    let mut file = BufWriter::new(File::create(&temp_file)?);
+    file.write_all(&reader.raw_header().to_bytes()?)?;
+    for block in 0..reader.block_count() {
+        file.write_all(&reader.decode(block)?)?;
+    }
+    file.flush()?;
+    drop(file);
+
+    commit_temp(&temp_file, dest)?;
+    Ok(reader.len() as u64)
+}
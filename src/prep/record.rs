@@ -0,0 +1,180 @@
+use std::io::{self, Write};
+
+use crate::prep::header::{PriceWidth, RecordLayout};
+
+// Volumes in the OHLCV layout are fixed point with 8 decimals, like the Binance dumps.
+pub const VOLUME_SCALE: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolanaPriceEntry {
+    pub time: u32, // Timestamp in seconds since epoch.
+    pub close_price: u64, // Integer price with the file's price scale (3 decimals by default) included.
+}
+
+/** One full kline, prices at the file's price scale and volumes at `VOLUME_SCALE`. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OhlcvEntry {
+    pub time: u32,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub volume: u64,                 // Base asset (SOL).
+    pub quote_volume: u64,           // Quote asset (USDT).
+    pub trades: u32,
+    pub taker_buy_base_volume: u64,
+    pub taker_buy_quote_volume: u64,
+}
+
+/** Fixed-width little-endian record that can be stored in a `.dat` file. */
+pub trait Record: Copy {
+    const LAYOUT: RecordLayout;
+
+    fn time(&self) -> u32;
+    /** Largest price in the record, checked against the stored price width before writing. */
+    fn max_price(&self) -> u64;
+    fn close(&self) -> u64;
+    /** Record `densify` writes for a second without data, every price set to `price`. */
+    fn filled(time: u32, price: u64) -> Self;
+    fn write_le<W: Write>(&self, writer: &mut W, width: PriceWidth) -> io::Result<()>;
+    fn from_le_bytes(buffer: &[u8], width: PriceWidth) -> Self;
+}
+
+fn write_price<W: Write>(writer: &mut W, price: u64, width: PriceWidth) -> io::Result<()> {
+    match width {
+        PriceWidth::U32 => writer.write_all(&(price as u32).to_le_bytes()),
+        PriceWidth::U64 => writer.write_all(&price.to_le_bytes()),
+    }
+}
+
+fn u32_at(buffer: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buffer[at], buffer[at + 1], buffer[at + 2], buffer[at + 3]])
+}
+
+fn u64_at(buffer: &[u8], at: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buffer[at..at + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn price_at(buffer: &[u8], at: usize, width: PriceWidth) -> u64 {
+    match width {
+        PriceWidth::U32 => u32_at(buffer, at) as u64,
+        PriceWidth::U64 => u64_at(buffer, at),
+    }
+}
+
+// Close record: u32 time, then the close price at the stored width.
+impl Record for SolanaPriceEntry {
+    const LAYOUT: RecordLayout = RecordLayout::Close;
+
+    fn time(&self) -> u32 {
+        self.time
+    }
+
+    fn max_price(&self) -> u64 {
+        self.close_price
+    }
+
+    fn close(&self) -> u64 {
+        self.close_price
+    }
+
+    fn filled(time: u32, price: u64) -> SolanaPriceEntry {
+        SolanaPriceEntry { time, close_price: price }
+    }
+
+    fn write_le<W: Write>(&self, writer: &mut W, width: PriceWidth) -> io::Result<()> {
+        writer.write_all(&self.time.to_le_bytes())?;
+        write_price(writer, self.close_price, width)
+    }
+
+    fn from_le_bytes(buffer: &[u8], width: PriceWidth) -> SolanaPriceEntry {
+        SolanaPriceEntry {
+            time: u32_at(buffer, 0),
+            close_price: price_at(buffer, 4, width),
+        }
+    }
+}
+
+// OHLCV record, 56 bytes with u32 prices (72 with u64, prices shift the rest along):
+//
+//  0..4   time          4..8   open         8..12  high         12..16 low
+//  16..20 close         20..28 volume       28..36 quote volume 36..40 trades
+//  40..48 taker buy base volume             48..56 taker buy quote volume
+impl Record for OhlcvEntry {
+    const LAYOUT: RecordLayout = RecordLayout::Ohlcv;
+
+    fn time(&self) -> u32 {
+        self.time
+    }
+
+    fn max_price(&self) -> u64 {
+        self.open.max(self.high).max(self.low).max(self.close)
+    }
+
+    fn close(&self) -> u64 {
+        self.close
+    }
+
+    // Flat candle without volume or trades.
+    fn filled(time: u32, price: u64) -> OhlcvEntry {
+        OhlcvEntry { time, open: price, high: price, low: price, close: price, ..OhlcvEntry::default() }
+    }
+
+    fn write_le<W: Write>(&self, writer: &mut W, width: PriceWidth) -> io::Result<()> {
+        writer.write_all(&self.time.to_le_bytes())?;
+        write_price(writer, self.open, width)?;
+        write_price(writer, self.high, width)?;
+        write_price(writer, self.low, width)?;
+        write_price(writer, self.close, width)?;
+        writer.write_all(&self.volume.to_le_bytes())?;
+        writer.write_all(&self.quote_volume.to_le_bytes())?;
+        writer.write_all(&self.trades.to_le_bytes())?;
+        writer.write_all(&self.taker_buy_base_volume.to_le_bytes())?;
+        writer.write_all(&self.taker_buy_quote_volume.to_le_bytes())
+    }
+
+    fn from_le_bytes(buffer: &[u8], width: PriceWidth) -> OhlcvEntry {
+        let w = width.bytes();
+        let rest = 4 + 4 * w;
+        OhlcvEntry {
+            time: u32_at(buffer, 0),
+            open: price_at(buffer, 4, width),
+            high: price_at(buffer, 4 + w, width),
+            low: price_at(buffer, 4 + 2 * w, width),
+            close: price_at(buffer, 4 + 3 * w, width),
+            volume: u64_at(buffer, rest),
+            quote_volume: u64_at(buffer, rest + 8),
+            trades: u32_at(buffer, rest + 16),
+            taker_buy_base_volume: u64_at(buffer, rest + 20),
+            taker_buy_quote_volume: u64_at(buffer, rest + 28),
+        }
+    }
+}
+
+impl SolanaPriceEntry {
+    /** Time and close out of a record of any layout. */
+    pub fn from_record(buffer: &[u8], layout: RecordLayout, width: PriceWidth) -> SolanaPriceEntry {
+        match layout {
+            RecordLayout::Close => <SolanaPriceEntry as Record>::from_le_bytes(buffer, width),
+            RecordLayout::Ohlcv => SolanaPriceEntry {
+                time: u32_at(buffer, 0),
+                close_price: price_at(buffer, 4 + 3 * width.bytes(), width),
+            },
+        }
+    }
+}
+
+impl From<OhlcvEntry> for SolanaPriceEntry {
+    fn from(entry: OhlcvEntry) -> SolanaPriceEntry {
+        SolanaPriceEntry { time: entry.time, close_price: entry.close }
+    }
+}
+
+// Flat candle, volumes and trades aren't known.
+impl From<SolanaPriceEntry> for OhlcvEntry {
+    fn from(entry: SolanaPriceEntry) -> OhlcvEntry {
+        OhlcvEntry::filled(entry.time, entry.close_price)
+    }
+}
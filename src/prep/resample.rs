@@ -0,0 +1,200 @@
+use std::{
+    fs::File,
+    iter::Peekable,
+};
+
+use crate::prep::atomic::{commit_temp, temp_path};
+use crate::prep::error::Error;
+use crate::prep::header::{write_header, FillPolicy, RecordLayout, DEFAULT_INTERVAL_SECS};
+use crate::prep::decimal::format_time;
+use crate::prep::merge::{write_records, OverlapPolicy};
+use crate::prep::reader::PriceReader;
+use crate::prep::record::{OhlcvEntry, SolanaPriceEntry};
+
+// Bars written per `write_records` call.
+const CHUNK_BARS: usize = 1 << 16;
+
+/** Bar size and where bars start. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResampleOptions {
+    pub interval_secs: u32,
+    pub offset_secs: u32, // Bars start at multiples of the interval from the UTC epoch plus this, 0 for plain UTC.
+}
+
+impl ResampleOptions {
+    /** Start of the bar `time` falls into. */
+    pub fn bar_start(&self, time: u32) -> u32 {
+        let interval = self.interval_secs as i64;
+        let shifted = time as i64 - self.offset_secs as i64;
+        (shifted - shifted.rem_euclid(interval) + self.offset_secs as i64).max(0) as u32
+    }
+}
+
+/** Parses an interval like `90`, `30s`, `5m`, `1h` or `1d` into seconds. */
+pub fn parse_interval(value: &str) -> Result<u32, Error> {
+    let (digits, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => value.split_at(at),
+        None => (value, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        _ => return Err(Error::Format(format!("Unknown interval unit in {:?}, use s, m, h or d", value))),
+    };
+    let count: u32 = digits.parse().map_err(|_| Error::Format(format!("Invalid interval {:?}", value)))?;
+    count
+        .checked_mul(multiplier)
+        .ok_or_else(|| Error::Format(format!("Interval {:?} is too long", value)))
+}
+
+/**
+ * Rolls consecutive records into bars: first open, highest high, lowest low, last close, summed volumes and trades.
+ * Each bar is stamped with its start, bars without records are left out. A sum that overflows is an error.
+ */
+pub struct Bars<I: Iterator<Item = OhlcvEntry>> {
+    entries: Peekable<I>,
+    options: ResampleOptions,
+}
+
+impl<I: Iterator<Item = OhlcvEntry>> Bars<I> {
+    pub fn new(entries: I, options: ResampleOptions) -> Bars<I> {
+        Bars { entries: entries.peekable(), options }
+    }
+}
+
+impl<I: Iterator<Item = OhlcvEntry>> Iterator for Bars<I> {
+    type Item = Result<OhlcvEntry, Error>;
+
+    fn next(&mut self) -> Option<Result<OhlcvEntry, Error>> {
+        let first = self.entries.next()?;
+        let start = self.options.bar_start(first.time);
+        let mut bar = OhlcvEntry { time: start, ..first };
+
+        while let Some(entry) = self.entries.next_if(|entry| self.options.bar_start(entry.time) == start) {
+            if let Err(err) = add_to_bar(&mut bar, &entry) {
+                return Some(Err(err));
+            }
+        }
+        Some(Ok(bar))
+    }
+}
+
+fn add_to_bar(bar: &mut OhlcvEntry, entry: &OhlcvEntry) -> Result<(), Error> {
+    let overflow = |field: &str| Error::Format(format!("Summed {} of the bar at {} overflows", field, format_time(bar.time)));
+    let sum = |total: u64, value: u64, field: &str| total.checked_add(value).ok_or_else(|| overflow(field));
+
+    bar.high = bar.high.max(entry.high);
+    bar.low = bar.low.min(entry.low);
+    bar.close = entry.close;
+    bar.volume = sum(bar.volume, entry.volume, "volume")?;
+    bar.quote_volume = sum(bar.quote_volume, entry.quote_volume, "quote volume")?;
+    bar.trades = bar.trades.checked_add(entry.trades).ok_or_else(|| overflow("trades"))?;
+    bar.taker_buy_base_volume = sum(bar.taker_buy_base_volume, entry.taker_buy_base_volume, "taker buy base volume")?;
+    bar.taker_buy_quote_volume = sum(bar.taker_buy_quote_volume, entry.taker_buy_quote_volume, "taker buy quote volume")?;
+    Ok(())
+}
+
+/**
+ * Resamples a merged `.dat` file into bars written to `dest` in the OHLCV layout, with the bar interval in the header.
+ * Close-only sources give OHLC from the closes and zero volume. Sentinel-filled seconds of a densified source are skipped.
+ */
+pub fn resample(source: &str, dest: &str, options: &ResampleOptions) -> Result<u64, Error> {
+    let reader = PriceReader::open(source)?;
+    let mut header = reader.raw_header();
+    let source_interval = header.interval_secs.max(DEFAULT_INTERVAL_SECS);
+
+    if options.interval_secs == 0 || !options.interval_secs.is_multiple_of(source_interval) {
+        return Err(Error::Format(format!(
+            "Bar interval {}s has to be a multiple of the source's {}s interval",
+            options.interval_secs, source_interval
+        )));
+    }
+
+    let sentinel = (header.fill == Some(FillPolicy::Sentinel)).then(|| header.price_width.max_value());
+    let format = header.format();
+    let entries: Box<dyn Iterator<Item = Result<OhlcvEntry, Error>>> = match reader.layout() {
+        RecordLayout::Close => Box::new(reader.records::<SolanaPriceEntry>(0, reader.len())?.map(|entry| entry.map(OhlcvEntry::from))),
+        RecordLayout::Ohlcv => Box::new(reader.records::<OhlcvEntry>(0, reader.len())?),
+    };
+    // Bars end at the first block that fails to decode, the error is returned once they're out of the way.
+    let mut failed = None;
+    let entries = entries
+        .map_while(|entry| entry.map_err(|err| failed = Some(err)).ok())
+        .filter(|entry| Some(entry.close) != sentinel);
+
+    // Starts out as an empty file with the header, `write_records` appends and keeps the counts.
+    header.layout = RecordLayout::Ohlcv;
+    header.interval_secs = options.interval_secs;
+    header.fill = None;
+    header.record_count = 0;
+    header.first_time = 0;
+    header.last_time = 0;
+
+    let temp_file = temp_path(dest);
+    write_header(&mut File::create(&temp_file)?, &header)?;
+
+    let mut count = 0;
+    let mut chunk = Vec::with_capacity(CHUNK_BARS);
+    for bar in Bars::new(entries, *options) {
+        chunk.push(bar?);
+        if chunk.len() == CHUNK_BARS {
+            write_records(&temp_file, &chunk, format, OverlapPolicy::Error)?;
+            count += chunk.len() as u64;
+            chunk.clear();
+        }
+    }
+    if let Some(err) = failed {
+        return Err(err);
+    }
+    write_records(&temp_file, &chunk, format, OverlapPolicy::Error)?;
+    count += chunk.len() as u64;
+
+    commit_temp(&temp_file, dest)?;
+    println!("Resampled {} records into {} bars of {}s", reader.len(), count, options.interval_secs);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prep::record::Record;
+
+    const MINUTES: ResampleOptions = ResampleOptions { interval_secs: 60, offset_secs: 0 };
+
+    fn candle(time: u32, close: u64, volume: u64, trades: u32) -> OhlcvEntry {
+        OhlcvEntry {
+            volume,
+            quote_volume: volume,
+            trades,
+            taker_buy_base_volume: volume,
+            taker_buy_quote_volume: volume,
+            ..OhlcvEntry::filled(time, close)
+        }
+    }
+
+    #[test]
+    fn bars_roll_up_prices_volumes_and_trades() {
+        let entries = [candle(60, 5, 1, 2), candle(61, 9, 2, 3), candle(119, 3, 3, 4), candle(185, 7, 4, 5)];
+        let bars: Vec<_> = Bars::new(entries.into_iter(), MINUTES).collect::<Result<_, _>>().unwrap();
+
+        let first = OhlcvEntry { open: 5, high: 9, low: 3, close: 3, ..candle(60, 0, 6, 9) };
+        assert_eq!(bars, vec![first, candle(180, 7, 4, 5)]);
+    }
+
+    #[test]
+    fn overflowing_sums_are_an_error() {
+        let volume = [candle(60, 5, u64::MAX, 0), candle(61, 5, 1, 0)];
+        let mut bars = Bars::new(volume.into_iter(), MINUTES);
+        assert!(matches!(bars.next(), Some(Err(Error::Format(_)))));
+
+        let trades = [candle(60, 5, 0, u32::MAX), candle(61, 5, 0, 1)];
+        let mut bars = Bars::new(trades.into_iter(), MINUTES);
+        assert!(matches!(bars.next(), Some(Err(Error::Format(_)))));
+
+        // The same sums split over two bars are fine.
+        let apart = [candle(60, 5, u64::MAX, u32::MAX), candle(120, 5, u64::MAX, u32::MAX)];
+        assert_eq!(Bars::new(apart.into_iter(), MINUTES).filter(Result::is_ok).count(), 2);
+    }
+}
@@ -0,0 +1,145 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::prep::error::Error;
+use crate::prep::ipc::ArrowMetadata;
+use crate::prep::record::SolanaPriceEntry;
+
+// Rows upserted per transaction.
+const TRANSACTION_ROWS: usize = 100_000;
+
+/** Rollup tables next to `prices`, with their bucket size in seconds. */
+pub const ROLLUPS: [(&str, u32); 3] = [("prices_minute", 60), ("prices_hour", 3_600), ("prices_day", 86_400)];
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS prices (time INTEGER PRIMARY KEY, close INTEGER NOT NULL);
+";
+
+/**
+ * Writes close records into a SQLite database: `prices(time, close)` keyed on the timestamp, prices as fixed point
+ * integers like in the `.dat` file, value / 10^`price_scale`. `prices_minute`, `prices_hour` and `prices_day` hold
+ * open, high, low, close and the record count per bucket, stamped with the bucket start. The symbol, source,
+ * interval and price scale go into `meta`.
+ */
+pub struct SqliteSink {
+    conn: Connection,
+    span: Option<(u32, u32)>, // First and last time written, the rollups are refreshed over it.
+    rows: u64,
+}
+
+impl SqliteSink {
+    /** Opens or creates the database, refusing one that holds another symbol, interval or price scale. */
+    pub fn open(path: &str, metadata: &ArrowMetadata, scale: u8) -> Result<SqliteSink, Error> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        for (table, _) in ROLLUPS {
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    time INTEGER PRIMARY KEY, open INTEGER NOT NULL, high INTEGER NOT NULL, low INTEGER NOT NULL,
+                    close INTEGER NOT NULL, records INTEGER NOT NULL
+                );",
+                table
+            ))?;
+        }
+
+        let mut pairs = metadata.pairs().to_vec();
+        pairs.push(("price_scale", scale.to_string()));
+
+        // Every key is checked before any is written, a refused run leaves `meta` as it was.
+        let mut stored_keys = 0;
+        for (key, value) in &pairs {
+            let stored: Option<String> =
+                conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0)).optional()?;
+            match stored {
+                Some(stored) if *key != "source" && stored != *value => {
+                    return Err(Error::Format(format!(
+                        "{} holds {} {}, can't merge {} {} into it",
+                        path, key, stored, key, value
+                    )));
+                }
+                Some(_) => stored_keys += 1,
+                None => {}
+            }
+        }
+        if stored_keys > 0 && stored_keys < pairs.len() {
+            return Err(Error::Format(format!(
+                "{} has no price scale in meta, it holds prices as REAL from an older build, rebuild it",
+                path
+            )));
+        }
+
+        let transaction = conn.unchecked_transaction()?;
+        for (key, value) in &pairs {
+            transaction.execute(
+                "INSERT INTO meta (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )?;
+        }
+        transaction.commit()?;
+
+        Ok(SqliteSink { conn, span: None, rows: 0 })
+    }
+
+    /** Upserts sorted records, `TRANSACTION_ROWS` per transaction. Prices past `i64::MAX` don't fit an INTEGER and are refused. */
+    pub fn write(&mut self, entries: &[SolanaPriceEntry]) -> Result<(), Error> {
+        if let Some(entry) = entries.iter().find(|entry| i64::try_from(entry.close_price).is_err()) {
+            return Err(Error::Format(format!(
+                "Price {} at {} doesn't fit a SQLite INTEGER",
+                entry.close_price, entry.time
+            )));
+        }
+
+        for chunk in entries.chunks(TRANSACTION_ROWS) {
+            let transaction = self.conn.transaction()?;
+            {
+                let mut upsert = transaction.prepare_cached(
+                    "INSERT INTO prices (time, close) VALUES (?1, ?2) ON CONFLICT(time) DO UPDATE SET close = excluded.close",
+                )?;
+                for entry in chunk {
+                    upsert.execute(params![entry.time, entry.close_price as i64])?;
+                }
+            }
+            transaction.commit()?;
+        }
+
+        if let (Some(first), Some(last)) = (entries.first(), entries.last()) {
+            self.span = Some(match self.span {
+                Some((from, to)) => (from.min(first.time), to.max(last.time)),
+                None => (first.time, last.time),
+            });
+        }
+        self.rows += entries.len() as u64;
+        Ok(())
+    }
+
+    /** Rebuilds the rollup buckets touched by this run, returns the number of records written. */
+    pub fn finish(mut self) -> Result<u64, Error> {
+        if let Some((from, to)) = self.span {
+            let transaction = self.conn.transaction()?;
+            for (table, secs) in ROLLUPS {
+                // Whole buckets, a bucket at either end may also hold rows from earlier runs.
+                let start = from - from % secs;
+                let end = (to - to % secs) as u64 + secs as u64;
+                transaction.execute(
+                    &format!(
+                        "INSERT INTO {table} (time, open, high, low, close, records)
+                        SELECT bucket.time, open.close, bucket.high, bucket.low, close.close, bucket.records
+                        FROM (
+                            SELECT time / {secs} * {secs} AS time, min(time) AS first_time, max(time) AS last_time,
+                                max(close) AS high, min(close) AS low, count(*) AS records
+                            FROM prices WHERE time >= ?1 AND time < ?2 GROUP BY 1
+                        ) AS bucket
+                        JOIN prices AS open ON open.time = bucket.first_time
+                        JOIN prices AS close ON close.time = bucket.last_time
+                        WHERE true
+                        ON CONFLICT(time) DO UPDATE SET open = excluded.open, high = excluded.high,
+                            low = excluded.low, close = excluded.close, records = excluded.records",
+                    ),
+                    params![start, end],
+                )?;
+            }
+            transaction.commit()?;
+        }
+        Ok(self.rows)
+    }
+}